@@ -1,4 +1,16 @@
-use crate::{cartridge::Rom, mem::Mem};
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    rc::Rc,
+};
+
+use crate::{
+    cartridge::Rom,
+    cdl::CodeDataLogger,
+    mem::{Mem, RamInit},
+    ppu::NesPPU,
+    region::Region,
+    scheduler::{EventKind, Scheduler},
+};
 
 //  _______________ $10000  _______________
 // | PRG-ROM       |       |               |
@@ -28,65 +40,672 @@ use crate::{cartridge::Rom, mem::Mem};
 // | Zero Page     |       |               |
 // |_______________| $0000 |_______________|
 
+pub mod test;
+
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
-const PPU_REG: u16 = 0x2000;
-const PPU_REG_MIRRORS_END: u16 = 0x3FFF;
+const PRG_RAM: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const OAM_DMA: u16 = 0x4014;
+const OAM_DATA: u16 = 0x2004;
+
+// Anything living on the CPU bus outside of the console RAM: PPU and APU
+// registers, controllers, mappers or debug devices. `addr` is the CPU
+// address already folded with the mirroring mask the device was registered
+// with.
+pub trait Device {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+    // what read would return, without its side effects
+    fn peek(&self, addr: u16) -> u8;
+
+    // bits of a read the device doesn't drive, they keep whatever was last
+    // on the data bus (bit 5 of $4015, bits 5-7 of $4016/$4017)
+    fn open_bus_mask(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    // Run up to `master_clock` before the CPU touches a register, devices
+    // that keep their own time (PPU, APU) do their work here.
+    fn catch_up(&mut self, _master_clock: u64) {}
+}
+
+// lets the owner keep a handle on a device it registered on the bus
+impl<T: Device> Device for Rc<RefCell<T>> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.borrow_mut().read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.borrow_mut().write(addr, data)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.borrow().peek(addr)
+    }
+
+    fn open_bus_mask(&self, addr: u16) -> u8 {
+        self.borrow().open_bus_mask(addr)
+    }
+
+    fn catch_up(&mut self, master_clock: u64) {
+        self.borrow_mut().catch_up(master_clock)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookKind {
+    Read,
+    Write,
+    Exec,
+}
+
+// Gets address, value and CPU cycle. Returning Some replaces the value read
+// or written (cheats), exec hooks can't change anything.
+pub type HookFn = Box<dyn FnMut(u16, u8, u64) -> Option<u8>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HookId(usize);
+
+struct Hook {
+    id: HookId,
+    kind: HookKind,
+    start: u16,
+    end: u16,
+    callback: HookFn,
+}
+
+// What a 256-byte page of the CPU address space resolves to. Direct pages
+// index straight into their backing memory, Shared pages hold more than one
+// device and fall back to walking the device list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Page {
+    Ram,
+    PrgRam(usize),
+    PrgRom(usize),
+    Device(usize),
+    Shared,
+    Unmapped,
+}
+
+struct DeviceMapping {
+    start: u16,
+    end: u16,
+    mask: u16,
+    device: Box<dyn Device>,
+}
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
+    prg_ram: [u8; 0x2000],
     rom: Rom,
+    // also registered as a device, kept here for typed access
+    ppu: Rc<RefCell<NesPPU>>,
+    pub ram_init: RamInit,
+    pub cdl: Option<Rc<RefCell<CodeDataLogger>>>,
+    // debugging aid: an access nothing answers to stops the CPU with a bus
+    // error instead of reading open bus
+    pub fatal_unmapped: bool,
+    bus_error: Option<String>,
+    devices: Vec<DeviceMapping>,
+    pages: [Page; 256],
+    // PRG-ROM offset of every page of $8000-$FFFF, what bank switching edits
+    prg_map: [usize; 128],
+    // last value driven on the data bus, returned by unmapped reads
+    open_bus: u8,
+    pub clock: Scheduler,
+    // CPU cycles already spent on bus accesses by the running instruction
+    access_cycles: u64,
+    // set from begin_instruction to the end of tick, accesses made outside
+    // (a frontend poking RAM between instructions) take no time
+    timed: bool,
+    // latched by a mapper IRQ event until the CPU takes it
+    mapper_irq: bool,
+    hooks: Vec<Hook>,
+    next_hook_id: usize,
+    // checked on every access so memory hooks cost nothing when unused
+    has_hooks: bool,
 }
 
 impl Bus {
     pub fn new(rom: Rom) -> Self {
-        Bus {
+        let ppu = Rc::new(RefCell::new(NesPPU::new(
+            rom.chr_rom.clone(),
+            rom.screen_mirroring,
+        )));
+        ppu.borrow_mut().set_region(rom.region);
+        let region = rom.region;
+        let mut bus = Bus {
             cpu_vram: [0; 2048],
+            prg_ram: [0; 0x2000],
             rom,
+            ppu: ppu.clone(),
+            ram_init: RamInit::default(),
+            cdl: None,
+            fatal_unmapped: false,
+            bus_error: None,
+            devices: Vec::new(),
+            pages: [Page::Unmapped; 256],
+            prg_map: [0; 128],
+            open_bus: 0,
+            clock: Scheduler::new(region.timing()),
+            access_cycles: 0,
+            timed: false,
+            mapper_irq: false,
+            hooks: Vec::new(),
+            next_hook_id: 0,
+            has_hooks: false,
+        };
+        let prg_len = bus.rom.prg_rom.len().max(1);
+        for i in 0..128 {
+            bus.prg_map[i] = (i * 0x100) % prg_len;
         }
+        bus.register_device(
+            PPU_REGISTERS,
+            PPU_REGISTERS_MIRRORS_END,
+            0x2007,
+            Box::new(ppu),
+        )
+        .expect("the PPU registers are clear of RAM");
+        bus
     }
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        if self.rom.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-            addr = addr % 0x4000;
+
+    fn rebuild_pages(&mut self) {
+        for page in 0..256usize {
+            let start = (page << 8) as u16;
+            let end = start | 0xFF;
+            self.pages[page] = match start {
+                RAM..=RAM_MIRRORS_END => Page::Ram,
+                PRG_RAM..=PRG_RAM_END => Page::PrgRam((start - PRG_RAM) as usize),
+                0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                    Page::PrgRom(self.prg_map[page - 0x80])
+                }
+                _ => Page::Unmapped,
+            };
+            if self.pages[page] == Page::Ram {
+                continue;
+            }
+
+            let overlapping: Vec<usize> = (0..self.devices.len())
+                .filter(|i| self.devices[*i].start <= end && start <= self.devices[*i].end)
+                .collect();
+            if overlapping.is_empty() {
+                continue;
+            }
+            let first = &self.devices[overlapping[0]];
+            self.pages[page] = if first.start <= start && end <= first.end {
+                Page::Device(overlapping[0])
+            } else {
+                Page::Shared
+            };
         }
-        self.rom.prg_rom[addr as usize]
     }
-}
 
-impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
-        match addr {
-            RAM..=RAM_MIRRORS_END => {
-                let converted = addr & 0b00000111_11111111;
-                self.cpu_vram[converted as usize]
+    // Bank switching: maps `size` bytes of PRG-ROM starting at `prg_offset`
+    // at `cpu_addr`. Both have to be 256 bytes aligned and the window has
+    // to fit in $8000-$FFFF.
+    pub fn map_prg_rom(
+        &mut self,
+        cpu_addr: u16,
+        size: usize,
+        prg_offset: usize,
+    ) -> Result<(), String> {
+        if self.rom.prg_rom.is_empty() {
+            return Err("no PRG-ROM to map".to_string());
+        }
+        if cpu_addr < 0x8000 || cpu_addr as usize + size > 0x10000 {
+            return Err(format!(
+                "PRG window {:04X}+{:X} is outside of $8000-$FFFF",
+                cpu_addr, size
+            ));
+        }
+        if cpu_addr & 0xFF != 0 || size & 0xFF != 0 || prg_offset & 0xFF != 0 {
+            return Err(format!(
+                "PRG window {:04X}+{:X} from {:X} isn't 256 bytes aligned",
+                cpu_addr, size, prg_offset
+            ));
+        }
+        // only the switched pages change, pages a device sits on keep it
+        let first = cpu_addr as usize >> 8;
+        for i in 0..(size >> 8) {
+            let offset = (prg_offset + i * 0x100) % self.rom.prg_rom.len();
+            self.prg_map[first - 0x80 + i] = offset;
+            if let Page::PrgRom(_) = self.pages[first + i] {
+                self.pages[first + i] = Page::PrgRom(offset);
             }
-            PPU_REG..=PPU_REG_MIRRORS_END => {
-                let _converted = addr & 0b00100000_00000111;
-                todo!("ppu not impl yet")
+        }
+        Ok(())
+    }
+
+    // Maps `device` on start..=end. Accesses are folded with `mask` before
+    // reaching the device, e.g. 0x2000..=0x3FFF with 0x2007 for the PPU
+    // registers. Devices registered later win over earlier ones and over
+    // the cartridge, but the console RAM at $0000-$1FFF can't be replaced.
+    pub fn register_device(
+        &mut self,
+        start: u16,
+        end: u16,
+        mask: u16,
+        device: Box<dyn Device>,
+    ) -> Result<(), String> {
+        if start > end {
+            return Err(format!("device range ${:04X}-${:04X} is empty", start, end));
+        }
+        if start <= RAM_MIRRORS_END {
+            return Err(format!(
+                "device range ${:04X}-${:04X} overlaps the console RAM at $0000-$1FFF",
+                start, end
+            ));
+        }
+        self.devices.insert(
+            0,
+            DeviceMapping {
+                start,
+                end,
+                mask,
+                device,
+            },
+        );
+        self.rebuild_pages();
+        Ok(())
+    }
+
+    fn device_index(&self, addr: u16) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|d| d.start <= addr && addr <= d.end)
+    }
+
+    // Picked from the ROM header by new, this forces another one. Takes
+    // effect right away, meant to be called before power_on.
+    pub fn set_region(&mut self, region: Region) {
+        self.clock.timing = region.timing();
+        self.ppu.borrow_mut().set_region(region);
+    }
+
+    pub fn region(&self) -> Region {
+        self.ppu.borrow().region()
+    }
+
+    pub fn power_on(&mut self) {
+        self.ram_init
+            .fill(&mut [&mut self.cpu_vram[..], &mut self.prg_ram[..]]);
+        self.bus_error = None;
+        self.open_bus = 0;
+        self.clock.reset();
+        self.mapper_irq = false;
+        let mut ppu = self.ppu.borrow_mut();
+        let ppu = &mut *ppu;
+        self.ram_init.fill(&mut [
+            &mut ppu.vram[..],
+            &mut ppu.palette_table[..],
+            &mut ppu.oam_data[..],
+        ]);
+        ppu.power_on();
+    }
+
+    // RAM keeps its content across a reset
+    pub fn reset(&mut self) {
+        self.bus_error = None;
+        self.ppu.borrow_mut().reset();
+    }
+
+    pub fn enable_cdl(&mut self) {
+        let cdl = Rc::new(RefCell::new(CodeDataLogger::new(
+            self.rom.prg_rom.len(),
+            self.rom.chr_rom.len(),
+        )));
+        self.ppu.borrow_mut().cdl = Some(cdl.clone());
+        self.cdl = Some(cdl);
+    }
+
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 || self.rom.prg_rom.is_empty() {
+            return None;
+        }
+        Some(self.prg_map[(addr as usize >> 8) - 0x80] + (addr & 0xFF) as usize)
+    }
+
+    pub fn ppu(&self) -> Ref<'_, NesPPU> {
+        self.ppu.borrow()
+    }
+
+    pub fn ppu_mut(&mut self) -> RefMut<'_, NesPPU> {
+        self.ppu.borrow_mut()
+    }
+
+    // Brings the PPU up to now, true when it finished a frame since the
+    // last call. The picture is then in ppu().frame.
+    pub fn poll_frame(&mut self) -> bool {
+        let master = self.clock.master_clock();
+        let mut ppu = self.ppu.borrow_mut();
+        ppu.catch_up(master);
+        ppu.take_frame_ready()
+    }
+
+    // faults raised while executing an instruction, picked up by the CPU
+    // once the instruction is done
+    pub fn take_bus_error(&mut self) -> Option<String> {
+        self.bus_error.take()
+    }
+
+    // PRG-ROM currently mapped at $8000-$FFFF, one entry per run of pages
+    // that are contiguous in the ROM
+    pub fn mapper_banks(&self) -> String {
+        if self.rom.prg_rom.is_empty() {
+            return format!("mapper {}: no PRG-ROM", self.rom.mapper);
+        }
+        let mut ranges = Vec::new();
+        let mut first = 0;
+        for page in 1..=self.prg_map.len() {
+            if page < self.prg_map.len() && self.prg_map[page] == self.prg_map[page - 1] + 0x100 {
+                continue;
             }
-            0x8000..=0xFFFF => self.read_prg_rom(addr),
-            _ => {
-                println!("ignoring mem access at {}", addr);
-                0
+            let start = self.prg_map[first];
+            ranges.push(format!(
+                "${:04X}-${:04X} = PRG ${:05X}-${:05X}",
+                0x8000 + (first << 8),
+                0x8000 + (page << 8) - 1,
+                start,
+                start + ((page - first) << 8) - 1
+            ));
+            first = page;
+        }
+        format!("mapper {}: {}", self.rom.mapper, ranges.join(", "))
+    }
+}
+
+impl Bus {
+    // accesses to pages shared by several devices, or by a device and
+    // the cartridge
+    fn read_slow(&mut self, addr: u16) -> u8 {
+        match self.device_index(addr) {
+            Some(index) => self.read_device(index, addr),
+            None => {
+                if !self.cartridge_maps(addr) {
+                    self.unmapped_access("read from", addr);
+                }
+                self.peek_cartridge(addr)
             }
         }
     }
 
-    fn mem_write(&mut self, addr: u16, data: u8) {
+    fn peek_slow(&self, addr: u16) -> u8 {
+        match self.device_index(addr) {
+            Some(index) => self.peek_device(index, addr),
+            None => self.peek_cartridge(addr),
+        }
+    }
+
+    fn cartridge_maps(&self, addr: u16) -> bool {
+        (PRG_RAM..=PRG_RAM_END).contains(&addr) || self.prg_rom_offset(addr).is_some()
+    }
+
+    // nothing answers at `addr`, a bus error when that's set to be fatal
+    fn unmapped_access(&mut self, access: &str, addr: u16) {
+        if self.fatal_unmapped && self.bus_error.is_none() {
+            self.bus_error = Some(format!("{} unmapped ${:04X}", access, addr));
+        }
+    }
+
+    fn peek_cartridge(&self, addr: u16) -> u8 {
         match addr {
-            RAM..=RAM_MIRRORS_END => {
-                let converted = addr & 0b11111111111;
-                self.cpu_vram[converted as usize] = data;
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            0x8000..=0xFFFF => match self.prg_rom_offset(addr) {
+                Some(offset) => self.rom.prg_rom[offset],
+                None => self.open_bus,
+            },
+            _ => self.open_bus,
+        }
+    }
+
+    #[inline]
+    fn read_device(&mut self, index: usize, addr: u16) -> u8 {
+        let master = self.clock.master_clock();
+        let mapping = &mut self.devices[index];
+        let addr = addr & mapping.mask;
+        mapping.device.catch_up(master);
+        let open_bits = mapping.device.open_bus_mask(addr);
+        let data = mapping.device.read(addr);
+        (data & !open_bits) | (self.open_bus & open_bits)
+    }
+
+    fn peek_device(&self, index: usize, addr: u16) -> u8 {
+        let mapping = &self.devices[index];
+        let addr = addr & mapping.mask;
+        let open_bits = mapping.device.open_bus_mask(addr);
+        let data = mapping.device.peek(addr);
+        (data & !open_bits) | (self.open_bus & open_bits)
+    }
+
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.clock.cpu_cycles()
+    }
+
+    // Every access of an instruction takes a CPU cycle, so devices see
+    // reads and writes at the cycle they happen in rather than at the start
+    // of the instruction.
+    #[inline]
+    fn access_cycle(&mut self) {
+        if self.timed {
+            self.clock.advance_cpu(1);
+            self.access_cycles += 1;
+        }
+    }
+
+    // Starts timing accesses, until the tick ending the instruction (or
+    // interrupt, or reset sequence).
+    pub fn begin_instruction(&mut self) {
+        self.access_cycles = 0;
+        self.timed = true;
+    }
+
+    // Moves time forward to the end of an instruction that took `cycles`
+    // and fires whatever events came due meanwhile.
+    pub fn tick(&mut self, cycles: u8) {
+        let remaining = (cycles as u64).saturating_sub(self.access_cycles);
+        self.access_cycles = 0;
+        self.clock.advance_cpu(remaining);
+        while let Some(event) = self.clock.pop_due() {
+            match event {
+                EventKind::OamDma(page) => self.oam_dma(page),
+                EventKind::MapperIrq => self.mapper_irq = true,
             }
-            PPU_REG..=PPU_REG_MIRRORS_END => {
-                let _converted = addr & 0b00100000_00000111;
-                todo!("PPU is not supported yet");
+        }
+        self.timed = false;
+    }
+
+    // Catches the PPU up to now, true when it raised NMI since last asked.
+    // Called at every instruction boundary.
+    pub fn poll_nmi(&mut self) -> bool {
+        let master = self.clock.master_clock();
+        let mut ppu = self.ppu.borrow_mut();
+        ppu.catch_up(master);
+        ppu.take_nmi()
+    }
+
+    pub fn mapper_irq_pending(&self) -> bool {
+        self.mapper_irq
+    }
+
+    pub fn take_mapper_irq(&mut self) -> bool {
+        std::mem::take(&mut self.mapper_irq)
+    }
+
+    // brings every device up to the current time, e.g. at the end of a frame
+    pub fn sync_devices(&mut self) {
+        let master = self.clock.master_clock();
+        for mapping in self.devices.iter_mut() {
+            mapping.device.catch_up(master);
+        }
+    }
+
+    pub fn add_hook(&mut self, kind: HookKind, start: u16, end: u16, callback: HookFn) -> HookId {
+        let id = HookId(self.next_hook_id);
+        self.next_hook_id += 1;
+        self.hooks.push(Hook {
+            id,
+            kind,
+            start,
+            end,
+            callback,
+        });
+        self.has_hooks = true;
+        id
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) {
+        self.hooks.retain(|h| h.id != id);
+        self.has_hooks = !self.hooks.is_empty();
+    }
+
+    #[inline]
+    pub fn has_hooks(&self) -> bool {
+        self.has_hooks
+    }
+
+    pub fn run_hooks(&mut self, kind: HookKind, addr: u16, mut value: u8) -> u8 {
+        let cycles = self.cycles();
+        for hook in self.hooks.iter_mut() {
+            if hook.kind == kind && hook.start <= addr && addr <= hook.end {
+                if let Some(replaced) = (hook.callback)(addr, value, cycles) {
+                    if kind != HookKind::Exec {
+                        value = replaced;
+                    }
+                }
             }
-            0x8000..=0xFFFF => panic!("You can't write inside PRG rom hacker !"),
-            _ => {
-                println!("Ignoring mem write-access at {}", addr);
+        }
+        value
+    }
+
+    fn write_device(&mut self, index: usize, addr: u16, data: u8) {
+        let master = self.clock.master_clock();
+        let mapping = &mut self.devices[index];
+        mapping.device.catch_up(master);
+        mapping.device.write(addr & mapping.mask, data);
+    }
+
+    fn write_slow(&mut self, addr: u16, data: u8) {
+        if let Some(index) = self.device_index(addr) {
+            self.write_device(index, addr, data);
+            return;
+        }
+        match addr {
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize] = data,
+            _ if !self.cartridge_maps(addr) => self.unmapped_access("write to", addr),
+            _ => {}
+        }
+    }
+
+    // Copies page $XX00-$XXFF into OAM through OAMDATA, once the instruction
+    // that wrote $4014 is done. The CPU is halted for 513 cycles, plus one
+    // to line up with a read cycle when started on an odd one.
+    fn oam_dma(&mut self, page: u8) {
+        let stall = if self.cycles() % 2 == 1 { 514 } else { 513 };
+        let base = (page as u16) << 8;
+        // the copy runs on the CPU's time, even after an untimed $4014 write
+        self.timed = true;
+        for i in 0..=0xFF {
+            let data = self.mem_read(base | i);
+            self.mem_write(OAM_DATA, data);
+        }
+        // the 512 copy cycles went by through the accesses
+        self.access_cycles = 0;
+        self.clock.advance_cpu(stall - 512);
+    }
+}
+
+impl Mem for Bus {
+    #[inline]
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        let mut data = match self.pages[(addr >> 8) as usize] {
+            Page::Device(index) => self.read_device(index, addr),
+            Page::Shared => self.read_slow(addr),
+            Page::Unmapped => {
+                self.unmapped_access("read from", addr);
+                self.open_bus
+            }
+            _ => self.peek(addr),
+        };
+        if self.has_hooks {
+            data = self.run_hooks(HookKind::Read, addr, data);
+        }
+        self.open_bus = data;
+        self.access_cycle();
+        data
+    }
+
+    #[inline]
+    fn peek(&self, addr: u16) -> u8 {
+        let low = (addr & 0xFF) as usize;
+        match self.pages[(addr >> 8) as usize] {
+            Page::Ram => self.cpu_vram[(addr & 0b00000111_11111111) as usize],
+            Page::PrgRam(base) => self.prg_ram[base + low],
+            Page::PrgRom(base) => self.rom.prg_rom[base + low],
+            Page::Device(index) => self.peek_device(index, addr),
+            Page::Shared => self.peek_slow(addr),
+            Page::Unmapped => self.open_bus,
+        }
+    }
+
+    #[inline]
+    fn mem_write(&mut self, addr: u16, mut data: u8) {
+        let low = (addr & 0xFF) as usize;
+        if self.has_hooks {
+            data = self.run_hooks(HookKind::Write, addr, data);
+        }
+        self.open_bus = data;
+        if addr == OAM_DMA {
+            self.clock.schedule_in(0, EventKind::OamDma(data));
+            self.access_cycle();
+            return;
+        }
+        match self.pages[(addr >> 8) as usize] {
+            Page::Ram => self.cpu_vram[(addr & 0b11111111111) as usize] = data,
+            Page::PrgRam(base) => self.prg_ram[base + low] = data,
+            Page::Device(index) => self.write_device(index, addr, data),
+            Page::Shared => self.write_slow(addr, data),
+            // writes to ROM go nowhere, mappers register as devices to see
+            // their bank registers
+            Page::PrgRom(_) => {}
+            Page::Unmapped => self.unmapped_access("write to", addr),
+        }
+        self.access_cycle();
+    }
+
+    // one dispatch when both bytes sit in the same direct page
+    #[inline]
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        if pos & 0xFF != 0xFF && !self.has_hooks {
+            let low = (pos & 0xFF) as usize;
+            match self.pages[(pos >> 8) as usize] {
+                Page::Ram => {
+                    let i = (pos & 0b00000111_11111111) as usize;
+                    let (lo, hi) = (self.cpu_vram[i], self.cpu_vram[i + 1]);
+                    self.open_bus = hi;
+                    self.access_cycle();
+                    self.access_cycle();
+                    return (hi as u16) << 8 | lo as u16;
+                }
+                Page::PrgRom(base) => {
+                    let prg = &self.rom.prg_rom;
+                    let (lo, hi) = (prg[base + low], prg[base + low + 1]);
+                    self.open_bus = hi;
+                    self.access_cycle();
+                    self.access_cycle();
+                    return (hi as u16) << 8 | lo as u16;
+                }
+                _ => {}
             }
         }
+        let lo = self.mem_read(pos) as u16;
+        let hi = self.mem_read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
     }
 }
@@ -0,0 +1,419 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    bus::{Bus, Device, HookKind},
+    cartridge::test::gen_test_rom_with_program,
+    cpu::CPU,
+    mem::Mem,
+    scheduler::EventKind,
+};
+
+// register 2 behaves like PPUSTATUS: reading it clears bit 7
+#[derive(Default)]
+pub struct TestDevice {
+    pub regs: [u8; 8],
+    pub writes: Vec<(u16, u8)>,
+    pub reads: usize,
+}
+
+impl TestDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for TestDevice {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.peek(addr);
+        self.reads += 1;
+        if addr & 0x07 == 2 {
+            self.regs[2] &= 0x7F;
+        }
+        data
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.regs[(addr & 0x07) as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.regs[(addr & 0x07) as usize] = data;
+        self.writes.push((addr, data));
+    }
+}
+
+#[test]
+fn test_device_mirroring() {
+    let device = Rc::new(RefCell::new(TestDevice::new()));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.register_device(0x2000, 0x3FFF, 0x2007, Box::new(device.clone()))
+        .unwrap();
+
+    bus.mem_write(0x2001, 0x11);
+    bus.mem_write(0x3FFA, 0x22);
+    assert_eq!(bus.mem_read(0x2009), 0x11);
+    assert_eq!(bus.mem_read(0x2002), 0x22);
+    assert_eq!(device.borrow().writes, vec![(0x2001, 0x11), (0x2002, 0x22)]);
+}
+
+#[test]
+fn test_device_overrides_cartridge() {
+    let device = Rc::new(RefCell::new(TestDevice::new()));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[0xea]));
+    assert_eq!(bus.mem_read(0x8000), 0xea);
+
+    bus.register_device(0x8000, 0xFFFF, 0xFFFF, Box::new(device.clone()))
+        .unwrap();
+    bus.mem_write(0x8000, 0x05);
+    assert_eq!(bus.mem_read(0x8000), 0x05);
+    assert_eq!(bus.take_bus_error(), None);
+}
+
+#[test]
+fn test_device_over_ram_is_rejected() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    // RAM is on the board and can't be replaced
+    let err = bus
+        .register_device(0x0000, 0x1FFF, 0x07FF, Box::new(TestDevice::new()))
+        .unwrap_err();
+    assert!(err.contains("overlaps the console RAM"));
+    assert!(bus
+        .register_device(0x1F00, 0x2FFF, 0xFFFF, Box::new(TestDevice::new()))
+        .is_err());
+    assert!(bus
+        .register_device(0x5000, 0x4FFF, 0xFFFF, Box::new(TestDevice::new()))
+        .is_err());
+
+    bus.mem_write(0x0001, 0x42);
+    assert_eq!(bus.mem_read(0x0801), 0x42);
+}
+
+#[test]
+fn test_shared_page() {
+    let apu = Rc::new(RefCell::new(TestDevice::new()));
+    let joypad = Rc::new(RefCell::new(TestDevice::new()));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.register_device(0x4000, 0x4015, 0x4007, Box::new(apu.clone()))
+        .unwrap();
+    bus.register_device(0x4016, 0x4017, 0xFFFF, Box::new(joypad.clone()))
+        .unwrap();
+
+    bus.mem_write(0x4003, 0x10);
+    bus.mem_write(0x4016, 0x01);
+    assert_eq!(apu.borrow().writes, vec![(0x4003, 0x10)]);
+    assert_eq!(joypad.borrow().writes, vec![(0x4016, 0x01)]);
+}
+
+#[test]
+fn test_prg_bank_switching() {
+    let mut program = vec![0; 0x8000];
+    program[0x0000] = 0x11;
+    program[0x4000] = 0x22;
+    program[0x40FF] = 0x34;
+    program[0x4100] = 0x12;
+    let mut bus = Bus::new(gen_test_rom_with_program(&program));
+    assert_eq!(bus.mem_read(0x8000), 0x11);
+    assert_eq!(bus.mem_read_u16(0xC0FF), 0x1234);
+
+    bus.map_prg_rom(0x8000, 0x4000, 0x4000).unwrap();
+    assert_eq!(bus.mem_read(0x8000), 0x22);
+    assert_eq!(bus.mem_read_u16(0x80FF), 0x1234);
+    assert_eq!(bus.prg_rom_offset(0x8001), Some(0x4001));
+    assert_eq!(bus.mem_read(0xC000), 0x22);
+
+    // a device over part of the window keeps its pages
+    let device = Rc::new(RefCell::new(TestDevice::new()));
+    bus.register_device(0x8000, 0x80FF, 0x8007, Box::new(device.clone()))
+        .unwrap();
+    bus.map_prg_rom(0x8000, 0x4000, 0).unwrap();
+    device.borrow_mut().regs[1] = 0x99;
+    assert_eq!(bus.mem_read(0x8001), 0x99);
+    assert_eq!(bus.mem_read(0x8100), 0x00);
+    assert_eq!(bus.prg_rom_offset(0x8001), Some(0x0001));
+}
+
+#[test]
+fn test_prg_write_is_ignored() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[0xea]));
+    bus.fatal_unmapped = true;
+    bus.mem_write(0x8000, 0x12);
+    assert_eq!(bus.take_bus_error(), None);
+    assert_eq!(bus.mem_read(0x8000), 0xea);
+}
+
+#[test]
+fn test_fatal_unmapped() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.mem_read(0x5000);
+    bus.mem_write(0x4018, 0x01);
+    assert_eq!(bus.take_bus_error(), None);
+
+    bus.fatal_unmapped = true;
+    bus.mem_write(0x5000, 0x01);
+    assert_eq!(
+        bus.take_bus_error(),
+        Some("write to unmapped $5000".to_string())
+    );
+    // on a page shared with a device
+    bus.register_device(0x4016, 0x4017, 0xFFFF, Box::new(TestDevice::new()))
+        .unwrap();
+    bus.mem_read(0x4016);
+    assert_eq!(bus.take_bus_error(), None);
+    bus.mem_read(0x4018);
+    assert_eq!(
+        bus.take_bus_error(),
+        Some("read from unmapped $4018".to_string())
+    );
+}
+
+#[test]
+fn test_prg_bank_switching_rejects_bad_windows() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    assert!(bus.map_prg_rom(0x6000, 0x2000, 0).is_err());
+    assert!(bus.map_prg_rom(0xC000, 0x8000, 0).is_err());
+    assert!(bus.map_prg_rom(0x8010, 0x1000, 0).is_err());
+
+    let mut rom = gen_test_rom_with_program(&[]);
+    rom.prg_rom.clear();
+    let mut bus = Bus::new(rom);
+    assert!(bus.map_prg_rom(0x8000, 0x4000, 0).is_err());
+}
+
+#[test]
+fn test_ram_u16_fast_path() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.mem_write_u16(0x0810, 0xBEEF);
+    assert_eq!(bus.mem_read_u16(0x0010), 0xBEEF);
+    bus.mem_write_u16(0x00FF, 0x1234);
+    assert_eq!(bus.mem_read_u16(0x00FF), 0x1234);
+}
+
+struct PartialDevice;
+
+impl Device for PartialDevice {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, _addr: u16) -> u8 {
+        0b0000_0001
+    }
+
+    fn write(&mut self, _addr: u16, _data: u8) {}
+
+    fn open_bus_mask(&self, _addr: u16) -> u8 {
+        0b1110_0000
+    }
+}
+
+#[test]
+fn test_open_bus() {
+    // LDA $4018 after fetching the operand: the high byte $40 is the last
+    // value seen on the bus
+    let mut bus = Bus::new(gen_test_rom_with_program(&[0xad, 0x18, 0x40]));
+    assert_eq!(bus.mem_read(0x8002), 0x40);
+    assert_eq!(bus.mem_read(0x4018), 0x40);
+
+    bus.mem_write(0x0000, 0x5A);
+    assert_eq!(bus.mem_read(0x5000), 0x5A);
+    assert_eq!(bus.open_bus(), 0x5A);
+
+    bus.register_device(0x4016, 0x4017, 0xFFFF, Box::new(PartialDevice))
+        .unwrap();
+    bus.mem_write(0x0000, 0xFF);
+    assert_eq!(bus.mem_read(0x4016), 0b1110_0001);
+    bus.mem_write(0x0000, 0x40);
+    assert_eq!(bus.mem_read(0x4016), 0b0100_0001);
+}
+
+#[test]
+fn test_peek_has_no_side_effects() {
+    let device = Rc::new(RefCell::new(TestDevice::new()));
+    device.borrow_mut().regs[2] = 0x80;
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.register_device(0x2000, 0x3FFF, 0x2007, Box::new(device.clone()))
+        .unwrap();
+    bus.mem_write(0x0000, 0x12);
+
+    assert_eq!(bus.peek(0x2002), 0x80);
+    assert_eq!(bus.peek(0x200A), 0x80);
+    assert_eq!(device.borrow().reads, 0);
+    assert_eq!(bus.open_bus(), 0x12);
+
+    assert_eq!(bus.mem_read(0x2002), 0x80);
+    assert_eq!(bus.mem_read(0x2002), 0x00);
+    assert_eq!(device.borrow().reads, 2);
+}
+
+#[test]
+fn test_memory_hooks() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    assert!(!bus.has_hooks());
+
+    let log = seen.clone();
+    let watch = bus.add_hook(
+        HookKind::Write,
+        0x0300,
+        0x03FF,
+        Box::new(move |addr, value, cycle| {
+            log.borrow_mut().push((addr, value, cycle));
+            None
+        }),
+    );
+    // infinite lives
+    bus.add_hook(HookKind::Read, 0x0042, 0x0042, Box::new(|_, _, _| Some(9)));
+    bus.tick(7);
+
+    bus.mem_write(0x0300, 0x01);
+    bus.mem_write(0x0400, 0x02);
+    bus.mem_write(0x0042, 0x00);
+    assert_eq!(*seen.borrow(), vec![(0x0300, 0x01, 7)]);
+    assert_eq!(bus.mem_read(0x0042), 9);
+    assert_eq!(bus.mem_read_u16(0x0042), 9);
+    assert_eq!(bus.peek(0x0042), 0);
+
+    bus.remove_hook(watch);
+    bus.mem_write(0x0301, 0x03);
+    assert_eq!(seen.borrow().len(), 1);
+}
+
+#[test]
+fn test_exec_hooks() {
+    let fetched = Rc::new(RefCell::new(Vec::new()));
+    // LDA #$01 ; TAX ; BRK
+    let bus = Bus::new(gen_test_rom_with_program(&[0xa9, 0x01, 0xaa, 0x00]));
+    let mut cpu = CPU::new(bus);
+    let log = fetched.clone();
+    cpu.bus.add_hook(
+        HookKind::Exec,
+        0x8000,
+        0xFFFF,
+        Box::new(move |addr, opcode, cycle| {
+            log.borrow_mut().push((addr, opcode, cycle));
+            None
+        }),
+    );
+    cpu.power_on();
+    cpu.run_with_cb(|_| {});
+    assert_eq!(
+        *fetched.borrow(),
+        vec![(0x8000, 0xa9, 7), (0x8002, 0xaa, 9), (0x8003, 0x00, 11)]
+    );
+}
+
+#[test]
+fn test_accesses_outside_instructions_are_free() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.mem_write(0x00FE, 0x01);
+    assert_eq!(bus.cycles(), 0);
+
+    bus.begin_instruction();
+    bus.mem_read(0x00FE);
+    assert_eq!(bus.cycles(), 1);
+    bus.tick(3);
+    assert_eq!(bus.cycles(), 3);
+
+    // a frontend writing input once the instruction is done
+    bus.mem_write(0x00FE, 0x02);
+    bus.tick(0);
+    assert_eq!(bus.cycles(), 3);
+}
+
+#[test]
+fn test_oam_dma() {
+    let device = Rc::new(RefCell::new(TestDevice::new()));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.register_device(0x2000, 0x3FFF, 0x2007, Box::new(device.clone()))
+        .unwrap();
+    for i in 0..=0xFF {
+        bus.mem_write(0x0200 + i, i as u8);
+    }
+    // written from outside an instruction, that took no time
+    assert_eq!(bus.cycles(), 0);
+    let start = bus.cycles();
+
+    // the copy waits for the instruction writing $4014 to finish
+    bus.begin_instruction();
+    bus.mem_write(0x4014, 0x02);
+    assert!(device.borrow().writes.is_empty());
+    bus.tick(4);
+    {
+        let writes = &device.borrow().writes;
+        assert_eq!(writes.len(), 256);
+        assert!(writes
+            .iter()
+            .enumerate()
+            .all(|(i, w)| *w == (0x2004, i as u8)));
+    }
+    assert_eq!(bus.cycles() - start, 4 + 513);
+
+    // started on an odd cycle: one extra alignment cycle
+    bus.begin_instruction();
+    bus.mem_write(0x4014, 0x02);
+    bus.tick(4);
+    assert_eq!(bus.cycles() - start, 4 + 513 + 4 + 514);
+}
+
+// counts PPU dots, only when the bus makes it catch up
+struct LazyDevice {
+    synced_to: u64,
+}
+
+impl Device for LazyDevice {
+    fn read(&mut self, _addr: u16) -> u8 {
+        (self.synced_to / 4) as u8
+    }
+
+    fn write(&mut self, _addr: u16, _data: u8) {}
+
+    fn peek(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn catch_up(&mut self, master_clock: u64) {
+        self.synced_to = master_clock;
+    }
+}
+
+#[test]
+fn test_device_catch_up() {
+    let device = Rc::new(RefCell::new(LazyDevice { synced_to: 0 }));
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.register_device(0x2000, 0x3FFF, 0x2007, Box::new(device.clone()))
+        .unwrap();
+
+    bus.tick(7);
+    assert_eq!(device.borrow().synced_to, 0);
+    assert_eq!(bus.mem_read(0x2002), 21);
+
+    bus.tick(2);
+    bus.sync_devices();
+    assert_eq!(device.borrow().synced_to, 9 * 12);
+}
+
+#[test]
+fn test_mapper_irq_event() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.clock.schedule_in(5, EventKind::MapperIrq);
+    bus.tick(4);
+    assert!(!bus.take_mapper_irq());
+    bus.tick(2);
+    assert!(bus.take_mapper_irq());
+    assert!(!bus.take_mapper_irq());
+}
+
+#[test]
+fn test_mapper_banks() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    assert_eq!(
+        bus.mapper_banks(),
+        "mapper 0: $8000-$FFFF = PRG $00000-$07FFF"
+    );
+
+    bus.map_prg_rom(0xC000, 0x4000, 0x0000).unwrap();
+    assert_eq!(
+        bus.mapper_banks(),
+        "mapper 0: $8000-$BFFF = PRG $00000-$03FFF, $C000-$FFFF = PRG $00000-$03FFF"
+    );
+}
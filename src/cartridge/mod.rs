@@ -1,10 +1,13 @@
 use std::{fs::File, io::Write};
+
+use crate::region::Region;
+
 pub mod test;
 
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 pub const PRG_ROM_PAGE_SIZE: usize = 16 * 1024;
 pub const CHR_ROM_PAGE_SIZE: usize = 8 * 1024;
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     VERTICAL,
     HORIZONTAL,
@@ -16,6 +19,8 @@ pub struct Rom {
     pub chr_rom: Vec<u8>,
     pub mapper: u8,
     pub screen_mirroring: Mirroring,
+    // from the NES 2.0 header, iNES 1.0 files are taken as NTSC
+    pub region: Region,
 }
 
 impl Rom {
@@ -62,9 +67,17 @@ impl Rom {
         }
         let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
         let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("iNES2.0 is not supported yet".to_string());
-        }
+        let region = match ines_ver {
+            0 => Region::Ntsc,
+            2 => {
+                // sizes past 4 MB (byte 9) aren't supported
+                if raw[9] != 0 {
+                    return Err("NES 2.0 ROM sizes over 4MB are not supported".to_string());
+                }
+                Region::from_nes2_timing(raw[12])
+            }
+            _ => return Err("unknown iNES header version".to_string()),
+        };
 
         let four_screen = raw[6] & 0b1000 != 0;
         let vertical_mirroring = raw[6] & 0b1 != 0;
@@ -86,6 +99,7 @@ impl Rom {
             chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
             mapper,
             screen_mirroring,
+            region,
         })
     }
 }
@@ -1,4 +1,4 @@
-use crate::cartridge::Mirroring;
+use crate::{cartridge::Mirroring, region::Region};
 
 use super::{Rom, CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
 
@@ -37,6 +37,23 @@ pub fn gen_test_rom() -> Rom {
     Rom::new(&test_rom).unwrap()
 }
 
+// program is mapped at $8000 and the reset vector points to it
+pub fn gen_test_rom_with_program(program: &[u8]) -> Rom {
+    let mut pgp_rom = vec![0; 2 * PRG_ROM_PAGE_SIZE];
+    pgp_rom[..program.len()].copy_from_slice(program);
+    pgp_rom[0x7FFC] = 0x00;
+    pgp_rom[0x7FFD] = 0x80;
+    let test_rom = create_rom(TestRom {
+        header: vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+        ],
+        trainer: None,
+        pgp_rom,
+        chr_rom: vec![0; 1 * CHR_ROM_PAGE_SIZE],
+    });
+    Rom::new(&test_rom).unwrap()
+}
+
 #[test]
 fn test() {
     let test_rom = create_rom(TestRom {
@@ -91,18 +108,40 @@ fn test_with_trainer() {
 }
 
 #[test]
-fn test_nes2_is_not_supported() {
+fn test_nes2_timing() {
+    for (timing, region) in [
+        (0, Region::Ntsc),
+        (1, Region::Pal),
+        (2, Region::Ntsc),
+        (3, Region::Dendy),
+    ] {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x8, 00, 00, 00, 00, timing, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; 1 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+        });
+        let rom = Rom::new(&test_rom).unwrap();
+        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.region, region);
+    }
+    assert_eq!(gen_test_rom().region, Region::Ntsc);
+}
+
+#[test]
+fn test_unknown_header_version() {
     let test_rom = create_rom(TestRom {
         header: vec![
-            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x8, 00, 00, 00, 00, 00, 00, 00, 00,
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x4, 00, 00, 00, 00, 00, 00, 00, 00,
         ],
         trainer: None,
         pgp_rom: vec![1; 1 * PRG_ROM_PAGE_SIZE],
         chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
     });
-    let rom = Rom::new(&test_rom);
-    match rom {
+    match Rom::new(&test_rom) {
         Result::Ok(_) => assert!(false, "should not load rom"),
-        Result::Err(str) => assert_eq!(str, "iNES2.0 is not supported yet"),
+        Result::Err(str) => assert_eq!(str, "unknown iNES header version"),
     }
 }
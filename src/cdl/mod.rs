@@ -0,0 +1,160 @@
+use std::{fs, io, path::Path};
+
+pub mod test;
+
+// FCEUX .cdl layout: one flag byte per PRG-ROM byte, followed by one flag
+// byte per CHR-ROM byte.
+//
+// PRG byte: xPdcAADC
+//   C  = executed as code (opcode or operand)
+//   D  = read as data
+//   AA = 8K window the byte was accessed through ($8000/$A000/$C000/$E000)
+//   c  = reached indirectly as code (JMP ($xxxx) target)
+//   d  = accessed indirectly as data ((zp),Y / (zp,X))
+//   P  = fetched as PCM sample data by the DMC
+//
+// CHR byte: xxxxxxRD
+//   D  = rendered by the PPU
+//   R  = read by the CPU through $2007
+
+bitflags! {
+    pub struct PrgFlags: u8 {
+        const CODE          = 0b0000_0001;
+        const DATA          = 0b0000_0010;
+        const BANK_MASK     = 0b0000_1100;
+        const INDIRECT_CODE = 0b0001_0000;
+        const INDIRECT_DATA = 0b0010_0000;
+        const PCM           = 0b0100_0000;
+    }
+}
+
+bitflags! {
+    pub struct ChrFlags: u8 {
+        const RENDERED = 0b0000_0001;
+        const READ     = 0b0000_0010;
+    }
+}
+
+pub struct CodeDataLogger {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+}
+
+impl CodeDataLogger {
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLogger {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+        }
+    }
+
+    pub fn load(path: &Path, prg_size: usize, chr_size: usize) -> Result<Self, String> {
+        let raw = fs::read(path).map_err(|e| format!("can't read cdl file: {}", e))?;
+        Self::from_bytes(&raw, prg_size, chr_size)
+    }
+
+    pub fn from_bytes(raw: &[u8], prg_size: usize, chr_size: usize) -> Result<Self, String> {
+        if raw.len() != prg_size + chr_size {
+            return Err(format!(
+                "cdl size mismatch: expected {} bytes, got {}",
+                prg_size + chr_size,
+                raw.len()
+            ));
+        }
+        Ok(CodeDataLogger {
+            prg: raw[..prg_size].to_vec(),
+            chr: raw[prg_size..].to_vec(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(self.prg.len() + self.chr.len());
+        res.extend(&self.prg);
+        res.extend(&self.chr);
+        res
+    }
+
+    pub fn reset(&mut self) {
+        self.prg.iter_mut().for_each(|b| *b = 0);
+        self.chr.iter_mut().for_each(|b| *b = 0);
+    }
+
+    fn mark_prg(&mut self, prg_offset: usize, cpu_addr: u16, flags: PrgFlags) {
+        if let Some(entry) = self.prg.get_mut(prg_offset) {
+            let bank = (((cpu_addr.wrapping_sub(0x8000)) >> 13) as u8 & 0b11) << 2;
+            *entry = (*entry & !PrgFlags::BANK_MASK.bits()) | flags.bits() | bank;
+        }
+    }
+
+    // FCEUX makes no difference between opcode and operand bytes, both are
+    // plain code.
+    pub fn log_opcode(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(prg_offset, cpu_addr, PrgFlags::CODE);
+    }
+
+    pub fn log_operand(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(prg_offset, cpu_addr, PrgFlags::CODE);
+    }
+
+    pub fn log_indirect_code(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(
+            prg_offset,
+            cpu_addr,
+            PrgFlags::CODE | PrgFlags::INDIRECT_CODE,
+        );
+    }
+
+    pub fn log_data(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(prg_offset, cpu_addr, PrgFlags::DATA);
+    }
+
+    pub fn log_indirect_data(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(
+            prg_offset,
+            cpu_addr,
+            PrgFlags::DATA | PrgFlags::INDIRECT_DATA,
+        );
+    }
+
+    pub fn log_pcm(&mut self, prg_offset: usize, cpu_addr: u16) {
+        self.mark_prg(prg_offset, cpu_addr, PrgFlags::DATA | PrgFlags::PCM);
+    }
+
+    pub fn log_chr_rendered(&mut self, chr_offset: usize) {
+        if let Some(entry) = self.chr.get_mut(chr_offset) {
+            *entry |= ChrFlags::RENDERED.bits();
+        }
+    }
+
+    pub fn log_chr_read(&mut self, chr_offset: usize) {
+        if let Some(entry) = self.chr.get_mut(chr_offset) {
+            *entry |= ChrFlags::READ.bits();
+        }
+    }
+
+    pub fn prg_flags(&self, prg_offset: usize) -> PrgFlags {
+        PrgFlags::from_bits_truncate(self.prg[prg_offset])
+    }
+
+    pub fn chr_flags(&self, chr_offset: usize) -> ChrFlags {
+        ChrFlags::from_bits_truncate(self.chr[chr_offset])
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        self.prg
+            .iter()
+            .filter(|b| **b & PrgFlags::CODE.bits() != 0)
+            .count()
+    }
+
+    pub fn data_bytes(&self) -> usize {
+        self.prg
+            .iter()
+            .filter(|b| **b & PrgFlags::DATA.bits() != 0)
+            .count()
+    }
+}
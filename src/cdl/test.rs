@@ -0,0 +1,129 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    cdl::{ChrFlags, CodeDataLogger, PrgFlags},
+    cpu::CPU,
+};
+
+#[test]
+fn test_logs_code_and_data() {
+    // LDA $8010 ; LDX #$01 ; BRK
+    let mut program = vec![0xad, 0x10, 0x80, 0xa2, 0x01, 0x00];
+    program.resize(0x11, 0);
+    program[0x10] = 0x42;
+    let mut bus = Bus::new(gen_test_rom_with_program(&program));
+    bus.enable_cdl();
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    cpu.run_with_cb(|_| {});
+
+    let cdl = cpu.bus.cdl.as_ref().unwrap().borrow();
+    for offset in 0..6 {
+        assert!(cdl.prg_flags(offset).contains(PrgFlags::CODE));
+    }
+    assert!(cdl.prg_flags(0x10).contains(PrgFlags::DATA));
+    assert!(!cdl.prg_flags(0x10).contains(PrgFlags::CODE));
+    assert_eq!(cdl.prg_flags(0x20).bits(), 0);
+    assert_eq!(cpu.register_a, 0x42);
+}
+
+#[test]
+fn test_writes_are_not_data() {
+    // LDA #$01 ; STA $8010 ; INC $8011 ; BRK
+    let mut bus = Bus::new(gen_test_rom_with_program(&[
+        0xa9, 0x01, 0x8d, 0x10, 0x80, 0xee, 0x11, 0x80, 0x00,
+    ]));
+    bus.enable_cdl();
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    cpu.run_with_cb(|_| {});
+
+    let cdl = cpu.bus.cdl.as_ref().unwrap().borrow();
+    assert_eq!(cdl.prg_flags(0x10).bits(), 0);
+    assert_eq!(cdl.prg_flags(0x11).bits(), 0);
+    // BRK is code too
+    assert!(cdl.prg_flags(0x08).contains(PrgFlags::CODE));
+}
+
+#[test]
+fn test_logs_indirect_data() {
+    // LDA #$20 ; STA $00 ; LDA #$80 ; STA $01 ; LDY #$02 ; LDA ($00),Y ; BRK
+    let mut program = vec![
+        0xa9, 0x20, 0x85, 0x00, 0xa9, 0x80, 0x85, 0x01, 0xa0, 0x02, 0xb1, 0x00, 0x00,
+    ];
+    program.resize(0x23, 0);
+    program[0x22] = 0x99;
+    let mut bus = Bus::new(gen_test_rom_with_program(&program));
+    bus.enable_cdl();
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    cpu.run_with_cb(|_| {});
+
+    let flags = cpu.bus.cdl.as_ref().unwrap().borrow().prg_flags(0x22);
+    assert!(flags.contains(PrgFlags::DATA | PrgFlags::INDIRECT_DATA));
+    assert_eq!(cpu.register_a, 0x99);
+}
+
+#[test]
+fn test_logs_chr() {
+    let program = vec![
+        0xa9, 0x00, 0x8d, 0x06, 0x20, // LDA #$00 ; STA $2006
+        0xa9, 0x10, 0x8d, 0x06, 0x20, // LDA #$10 ; STA $2006
+        0xad, 0x07, 0x20, // LDA $2007
+        0xa9, 0x08, 0x8d, 0x01, 0x20, // LDA #$08 ; STA $2001
+        0x4c, 0x12, 0x80, // JMP $8012
+        0x00, // BRK
+    ];
+    let mut bus = Bus::new(gen_test_rom_with_program(&program));
+    bus.enable_cdl();
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    let mut frames = 0;
+    cpu.run_with_cb(|cpu| {
+        if cpu.bus.poll_frame() {
+            frames += 1;
+            if frames == 2 {
+                cpu.program_counter = 0x8015;
+            }
+        }
+    });
+
+    // the background is all tile 0, $2007 read $0010
+    let cdl = cpu.bus.cdl.as_ref().unwrap().borrow();
+    assert_eq!(cdl.chr_flags(0x00), ChrFlags::RENDERED);
+    assert_eq!(cdl.chr_flags(0x0F), ChrFlags::RENDERED);
+    assert_eq!(cdl.chr_flags(0x10), ChrFlags::READ);
+    assert_eq!(cdl.chr_flags(0x20).bits(), 0);
+}
+
+#[test]
+fn test_bank_bits() {
+    let mut cdl = CodeDataLogger::new(0x8000, 0);
+    cdl.log_opcode(0x0000, 0x8000);
+    cdl.log_data(0x4000, 0xC000);
+    cdl.log_data(0x7FFF, 0xFFFF);
+    assert_eq!(cdl.prg[0x0000], 0b0000_0001);
+    assert_eq!(cdl.prg[0x4000], 0b0000_1010);
+    assert_eq!(cdl.prg[0x7FFF], 0b0000_1110);
+}
+
+#[test]
+fn test_save_load_roundtrip() {
+    let mut cdl = CodeDataLogger::new(0x4000, 0x2000);
+    cdl.log_opcode(0x10, 0x8010);
+    cdl.log_pcm(0x3000, 0xF000);
+    cdl.log_chr_rendered(0x100);
+    cdl.log_chr_read(0x100);
+
+    let raw = cdl.to_bytes();
+    assert_eq!(raw.len(), 0x6000);
+
+    let loaded = CodeDataLogger::from_bytes(&raw, 0x4000, 0x2000).unwrap();
+    assert!(loaded.prg_flags(0x10).contains(PrgFlags::CODE));
+    assert!(loaded.prg_flags(0x3000).contains(PrgFlags::PCM));
+    assert_eq!(loaded.chr_flags(0x100), ChrFlags::RENDERED | ChrFlags::READ);
+    assert_eq!(loaded.code_bytes(), 1);
+    assert_eq!(loaded.data_bytes(), 1);
+
+    assert!(CodeDataLogger::from_bytes(&raw, 0x8000, 0x2000).is_err());
+}
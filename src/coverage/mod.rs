@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use crate::cpu::AddressingMode;
+use crate::opcodes::{self, OpCode};
+use crate::trace::is_branch;
+
+pub mod test;
+
+const ALL_MODES: [AddressingMode; 10] = [
+    AddressingMode::Immediate,
+    AddressingMode::ZeroPage,
+    AddressingMode::ZeroPage_X,
+    AddressingMode::ZeroPage_Y,
+    AddressingMode::Absolute,
+    AddressingMode::Absolute_X,
+    AddressingMode::Absolute_Y,
+    AddressingMode::Indirect_X,
+    AddressingMode::Indirect_Y,
+    AddressingMode::NoneAddressing,
+];
+
+pub struct OpcodeCoverage {
+    pub executed: [u64; 256],
+    pub page_crossed: [u64; 256],
+    pub branch_taken: [u64; 256],
+    pub branch_not_taken: [u64; 256],
+    pub modes: HashMap<AddressingMode, u64>,
+}
+
+impl Default for OpcodeCoverage {
+    fn default() -> Self {
+        OpcodeCoverage {
+            executed: [0; 256],
+            page_crossed: [0; 256],
+            branch_taken: [0; 256],
+            branch_not_taken: [0; 256],
+            modes: HashMap::new(),
+        }
+    }
+}
+
+// only branches and indexed reads pay for a page crossing, same table
+// the CPU times them with
+fn can_cross_page(opcode: &OpCode) -> bool {
+    is_branch(opcode.code) || opcode.pays_page_cross()
+}
+
+impl OpcodeCoverage {
+    pub fn new() -> Self {
+        OpcodeCoverage::default()
+    }
+
+    pub fn record(&mut self, opcode: &OpCode, page_crossed: bool, branch_taken: bool) {
+        let code = opcode.code as usize;
+        self.executed[code] += 1;
+        *self.modes.entry(opcode.mode).or_insert(0) += 1;
+        if page_crossed {
+            self.page_crossed[code] += 1;
+        }
+        if is_branch(opcode.code) {
+            if branch_taken {
+                self.branch_taken[code] += 1;
+            } else {
+                self.branch_not_taken[code] += 1;
+            }
+        }
+    }
+
+    // accumulate runs, e.g. every ROM of a test suite
+    pub fn merge(&mut self, other: &OpcodeCoverage) {
+        for i in 0..256 {
+            self.executed[i] += other.executed[i];
+            self.page_crossed[i] += other.page_crossed[i];
+            self.branch_taken[i] += other.branch_taken[i];
+            self.branch_not_taken[i] += other.branch_not_taken[i];
+        }
+        for (mode, count) in &other.modes {
+            *self.modes.entry(*mode).or_insert(0) += count;
+        }
+    }
+
+    // opcodes the decoder knows about that never ran
+    pub fn missing_opcodes(&self) -> Vec<u8> {
+        let mut missing: Vec<u8> = opcodes::OPCODES_MAP
+            .keys()
+            .filter(|code| self.executed[**code as usize] == 0)
+            .copied()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    pub fn missing_modes(&self) -> Vec<AddressingMode> {
+        ALL_MODES
+            .iter()
+            .filter(|mode| !self.modes.contains_key(mode))
+            .copied()
+            .collect()
+    }
+
+    pub fn missing_page_crossings(&self) -> Vec<u8> {
+        let mut missing: Vec<u8> = opcodes::OPCODES_MAP
+            .values()
+            .filter(|op| can_cross_page(op) && self.page_crossed[op.code as usize] == 0)
+            .map(|op| op.code)
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    pub fn missing_branch_cases(&self) -> Vec<(u8, bool)> {
+        let mut missing = Vec::new();
+        for code in 0..=255u8 {
+            if !is_branch(code) {
+                continue;
+            }
+            if self.branch_taken[code as usize] == 0 {
+                missing.push((code, true));
+            }
+            if self.branch_not_taken[code as usize] == 0 {
+                missing.push((code, false));
+            }
+        }
+        missing
+    }
+
+    // 16x16 opcode matrix:
+    //   "  " not decoded, "--" decoded but never run, "##" run,
+    //   "p#" run but an indexed page crossing never happened,
+    //   "b#" branch only ever went one way
+    pub fn report(&self) -> String {
+        let mut out = String::from("    ");
+        for lo in 0..16 {
+            out.push_str(&format!(" x{:X}", lo));
+        }
+        out.push('\n');
+
+        for hi in 0..16 {
+            out.push_str(&format!("{:X}x |", hi));
+            for lo in 0..16 {
+                let code = (hi << 4 | lo) as u8;
+                let cell = match opcodes::OPCODES_MAP.get(&code) {
+                    None => "  ",
+                    Some(_) if self.executed[code as usize] == 0 => "--",
+                    Some(_)
+                        if is_branch(code)
+                            && (self.branch_taken[code as usize] == 0
+                                || self.branch_not_taken[code as usize] == 0) =>
+                    {
+                        "b#"
+                    }
+                    Some(op) if can_cross_page(op) && self.page_crossed[code as usize] == 0 => "p#",
+                    Some(_) => "##",
+                };
+                out.push_str(&format!(" {}", cell));
+            }
+            out.push('\n');
+        }
+
+        let decoded = opcodes::OPCODES_MAP.len();
+        out.push_str(&format!(
+            "\nopcodes: {}/{} executed\n",
+            decoded - self.missing_opcodes().len(),
+            decoded
+        ));
+        out.push_str("addressing modes:\n");
+        for mode in ALL_MODES.iter() {
+            out.push_str(&format!(
+                "  {:<16}{}\n",
+                format!("{:?}", mode),
+                self.modes.get(mode).copied().unwrap_or(0)
+            ));
+        }
+        out
+    }
+}
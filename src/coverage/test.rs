@@ -0,0 +1,82 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    coverage::OpcodeCoverage,
+    cpu::{AddressingMode, CPU},
+};
+
+fn run_with_coverage(program: &[u8]) -> CPU {
+    let bus = Bus::new(gen_test_rom_with_program(program));
+    let mut cpu = CPU::new(bus);
+    cpu.coverage = Some(OpcodeCoverage::new());
+    cpu.power_on();
+    cpu.run_with_cb(|_| {});
+    cpu
+}
+
+#[test]
+fn test_records_opcodes_and_modes() {
+    // LDX #$01 ; LDA $02FF,X ; BRK
+    let cpu = run_with_coverage(&[0xa2, 0x01, 0xbd, 0xff, 0x02, 0x00]);
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    assert_eq!(coverage.executed[0xa2], 1);
+    assert_eq!(coverage.executed[0xbd], 1);
+    assert_eq!(coverage.page_crossed[0xbd], 1);
+    assert_eq!(coverage.modes[&AddressingMode::Immediate], 1);
+    assert_eq!(coverage.modes[&AddressingMode::Absolute_X], 1);
+    assert!(coverage.missing_opcodes().contains(&0xa9));
+    assert!(!coverage.missing_opcodes().contains(&0xbd));
+    assert!(!coverage.missing_page_crossings().contains(&0xbd));
+    assert!(!coverage.missing_page_crossings().contains(&0x9d));
+    assert!(coverage
+        .missing_modes()
+        .contains(&AddressingMode::Indirect_X));
+}
+
+#[test]
+fn test_records_branches() {
+    // LDX #$02 ; loop: DEX ; BNE loop ; BRK
+    let cpu = run_with_coverage(&[0xa2, 0x02, 0xca, 0xd0, 0xfd, 0x00]);
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    assert_eq!(coverage.executed[0xd0], 2);
+    assert_eq!(coverage.branch_taken[0xd0], 1);
+    assert_eq!(coverage.branch_not_taken[0xd0], 1);
+    assert_eq!(coverage.page_crossed[0xd0], 0);
+    assert!(!coverage.missing_branch_cases().contains(&(0xd0, true)));
+    assert!(coverage.missing_branch_cases().contains(&(0xf0, true)));
+}
+
+#[test]
+fn test_report_and_merge() {
+    let first = run_with_coverage(&[0xa2, 0x02, 0xca, 0xd0, 0xfd, 0x00]);
+    let second = run_with_coverage(&[0xa9, 0x01, 0x00]);
+
+    let mut total = OpcodeCoverage::new();
+    total.merge(first.coverage.as_ref().unwrap());
+    total.merge(second.coverage.as_ref().unwrap());
+    assert_eq!(total.executed[0xa9], 1);
+    assert_eq!(total.executed[0xca], 2);
+
+    let report = total.report();
+    let row_a = report.lines().find(|l| l.starts_with("Ax |")).unwrap();
+    // A0 LDY# never ran, A2 LDX# and A9 LDA# did
+    assert!(row_a.starts_with("Ax | -- -- ##"));
+    assert_eq!(&row_a[4 + 9 * 3..4 + 10 * 3], " ##");
+    let row_d = report.lines().find(|l| l.starts_with("Dx |")).unwrap();
+    assert!(row_d.starts_with("Dx | p#"));
+    assert!(report.contains("opcodes: 5/"));
+    assert_eq!(total.executed[0x00], 2);
+}
+
+#[test]
+fn test_page_cross_only_when_paid() {
+    // LDX #$01 ; STA $02FF,X ; BRK
+    let cpu = run_with_coverage(&[0xa2, 0x01, 0x9d, 0xff, 0x02, 0x00]);
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    // stores always take the extra cycle, there's no crossing to cover
+    assert_eq!(coverage.executed[0x9d], 1);
+    assert_eq!(coverage.page_crossed[0x9d], 0);
+}
@@ -1,13 +1,18 @@
-use crate::bus::Bus;
+use crate::bus::{Bus, HookKind};
+use crate::coverage::OpcodeCoverage;
 use crate::cpu::control_flow_ops::ControlOpCodes;
 use crate::cpu::logic_ops::LogicOpCodes;
 use crate::cpu::register_ops::RegisterOpCodes;
 use crate::cpu::stack_ops::StackOpCodes;
 use crate::cpu::status_ops::StatusOpCodes;
+use crate::crash::{write_crash_report, CrashReason, InstructionHistory};
+use crate::symbols::SymbolTable;
+use crate::trace::is_branch;
 
 use crate::mem::Mem;
 use crate::opcodes;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use self::other_ops::OtherOpCodes;
 pub mod control_flow_ops;
@@ -16,11 +21,20 @@ pub mod other_ops;
 pub mod register_ops;
 pub mod stack_ops;
 pub mod status_ops;
+pub mod test;
 
 const STACK_PTR_START: u16 = 0x0100;
 
 const STACK_PTR_RESET: u8 = 0xFD;
 
+// the reset sequence, vector fetch included
+const RESET_CYCLES: u8 = 7;
+const INTERRUPT_CYCLES: u8 = 7;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
 bitflags! {
 
   pub struct CpuFlags:u8{
@@ -35,7 +49,7 @@ bitflags! {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Immediate,
@@ -58,20 +72,37 @@ pub struct CPU {
     pub status: CpuFlags,
     pub program_counter: u16,
     pub bus: Bus,
+    pub coverage: Option<OpcodeCoverage>,
+    pub history: InstructionHistory,
+    pub crash_report_dir: PathBuf,
+    // labels for the addresses in crash reports
+    pub symbols: Option<SymbolTable>,
+    // taken branches and page crosses of the running instruction, on top
+    // of the opcode table's base count
+    extra_cycles: u8,
 }
 
 impl Mem for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data)
     }
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         self.bus.mem_read_u16(pos)
     }
 
+    fn peek_u16(&self, pos: u16) -> u16 {
+        self.bus.peek_u16(pos)
+    }
+
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         self.bus.mem_write_u16(pos, data)
     }
@@ -87,6 +118,11 @@ impl CPU {
             status: CpuFlags::from_bits_truncate(0b100100),
             program_counter: 0,
             bus,
+            coverage: None,
+            history: InstructionHistory::default(),
+            crash_report_dir: PathBuf::from("crash_reports"),
+            symbols: None,
+            extra_cycles: 0,
         }
     }
 
@@ -159,13 +195,16 @@ impl CPU {
         hi << 8 | lo
     }
 
+    // a taken branch costs 1 more cycle, 2 when it lands on another page
     fn branch_handle(&mut self, invariant: bool) {
         if invariant {
             let jump: i8 = self.mem_read(self.program_counter) as i8;
-            let jump_addr = self
-                .program_counter
-                .wrapping_add(1)
-                .wrapping_add(jump as u16);
+            let next = self.program_counter.wrapping_add(1);
+            let jump_addr = next.wrapping_add(jump as u16);
+            self.extra_cycles += 1;
+            if next & 0xFF00 != jump_addr & 0xFF00 {
+                self.extra_cycles += 1;
+            }
             self.program_counter = jump_addr;
         }
     }
@@ -181,51 +220,103 @@ impl CPU {
 
         self.update_zero_and_negative_flags(base.wrapping_sub(data));
     }
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
-        match mode {
-            AddressingMode::Immediate => self.program_counter,
-            AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
-            AddressingMode::ZeroPage_X => {
-                let pos = self.mem_read(self.program_counter);
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
+        let (pc, x, y) = (self.program_counter, self.register_x, self.register_y);
+        resolve_operand_address(mode, pc, x, y, |addr| self.mem_read(addr))
+    }
 
-                let addr = pos.wrapping_add(self.register_x) as u16;
-                addr
-            }
-            AddressingMode::ZeroPage_Y => {
-                let pos = self.mem_read(self.program_counter);
-                let addr = pos.wrapping_add(self.register_y) as u16;
-                addr
+    // same as get_operand_address without side effects, for instrumentation
+    fn peek_operand_address(&self, mode: &AddressingMode) -> u16 {
+        let (pc, x, y) = (self.program_counter, self.register_x, self.register_y);
+        resolve_operand_address(mode, pc, x, y, |addr| self.peek(addr))
+    }
+
+    // true when the indexed operand address lands on another page than its
+    // base, which costs an extra cycle on real hardware
+    fn page_crossed(&self, mode: &AddressingMode) -> bool {
+        let (base, addr) = match mode {
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y => (
+                self.peek_u16(self.program_counter),
+                self.peek_operand_address(mode),
+            ),
+            AddressingMode::Indirect_Y => {
+                let ptr = self.peek(self.program_counter);
+                let lowest = self.peek(ptr as u16);
+                let highest = self.peek(ptr.wrapping_add(1) as u16);
+                (
+                    (highest as u16) << 8 | (lowest as u16),
+                    self.peek_operand_address(mode),
+                )
             }
+            _ => return false,
+        };
+        base & 0xFF00 != addr & 0xFF00
+    }
 
-            AddressingMode::Absolute_X => {
-                let base = self.mem_read_u16(self.program_counter);
-                let addr = base.wrapping_add(self.register_x as u16);
-                addr
+    // program_counter points right after the opcode byte when called
+    fn log_code_data(&mut self, opcode: &opcodes::OpCode) {
+        let opcode_addr = self.program_counter.wrapping_sub(1);
+        let mut logged = Vec::with_capacity(3);
+        if let Some(offset) = self.bus.prg_rom_offset(opcode_addr) {
+            logged.push((offset, opcode_addr, false));
+        }
+        for i in 0..(opcode.len as u16 - 1) {
+            let operand_addr = self.program_counter.wrapping_add(i);
+            if let Some(offset) = self.bus.prg_rom_offset(operand_addr) {
+                logged.push((offset, operand_addr, true));
             }
-            AddressingMode::Absolute_Y => {
-                let base = self.mem_read_u16(self.program_counter);
-                let addr = base.wrapping_add(self.register_y as u16);
-                addr
+        }
+
+        let mut data = Vec::with_capacity(2);
+        match opcode.mode {
+            AddressingMode::Immediate => {}
+            AddressingMode::NoneAddressing => {
+                // JMP ($xxxx) reads its pointer as data
+                if opcode.code == 0x6c {
+                    let ptr = self.peek_u16(self.program_counter);
+                    data.push((ptr, false));
+                    data.push((ptr.wrapping_add(1), false));
+                }
             }
-            AddressingMode::Indirect_X => {
-                let base = self.mem_read(self.program_counter);
-                let ptr: u8 = (base as u8).wrapping_add(self.register_x);
-                let lowest = self.mem_read(ptr as u16);
-                let highest = self.mem_read(ptr.wrapping_add(1) as u16);
-                (highest as u16) << 8 | (lowest as u16)
+            // FCEUX only logs reads, stores and read-modify-write
+            // instructions leave their target alone
+            _ if opcode.writes_operand() => {}
+            AddressingMode::Indirect_X | AddressingMode::Indirect_Y => {
+                data.push((self.peek_operand_address(&opcode.mode), true));
             }
-            AddressingMode::Indirect_Y => {
-                let base = self.mem_read(self.program_counter);
+            _ => data.push((self.peek_operand_address(&opcode.mode), false)),
+        }
+        let data: Vec<(usize, u16, bool)> = data
+            .into_iter()
+            .filter_map(|(addr, indirect)| {
+                self.bus
+                    .prg_rom_offset(addr)
+                    .map(|offset| (offset, addr, indirect))
+            })
+            .collect();
 
-                let lowest = self.mem_read(base as u16);
-                let highest = self.mem_read((base as u8).wrapping_add(1) as u16);
-                let deref_base = (highest as u16) << 8 | (lowest as u16);
-                let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+        let mut cdl = self.bus.cdl.as_ref().unwrap().borrow_mut();
+        for (offset, addr, is_operand) in logged {
+            if is_operand {
+                cdl.log_operand(offset, addr);
+            } else {
+                cdl.log_opcode(offset, addr);
             }
-            AddressingMode::NoneAddressing => {
-                panic!("mode {:?} is not supported", mode)
+        }
+        for (offset, addr, indirect) in data {
+            if indirect {
+                cdl.log_indirect_data(offset, addr);
+            } else {
+                cdl.log_data(offset, addr);
+            }
+        }
+    }
+
+    fn log_indirect_jump_target(&mut self) {
+        let target = self.program_counter;
+        if let Some(offset) = self.bus.prg_rom_offset(target) {
+            if let Some(cdl) = &self.bus.cdl {
+                cdl.borrow_mut().log_indirect_code(offset, target);
             }
         }
     }
@@ -246,17 +337,50 @@ impl CPU {
     {
         let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
         loop {
+            self.extra_cycles = 0;
+            if self.bus.poll_nmi() {
+                self.interrupt(NMI_VECTOR);
+            } else if self.bus.mapper_irq_pending()
+                && !self.status.contains(CpuFlags::INTERRUPT_DISABLE)
+            {
+                self.bus.take_mapper_irq();
+                self.interrupt(IRQ_VECTOR);
+            }
+            // bus accesses made from outside (frontends writing input
+            // between instructions) take no time, only the instruction's do
+            self.bus.begin_instruction();
+
+            self.history.push(self.program_counter);
+            if self.bus.has_hooks() {
+                let code = self.bus.peek(self.program_counter);
+                self.bus
+                    .run_hooks(HookKind::Exec, self.program_counter, code);
+            }
             let code = self.mem_read(self.program_counter);
             self.program_counter += 1;
 
             let program_counter_state = self.program_counter;
-            let opcode = opcodes
-                .get(&code)
-                .expect(&format!("OpCode {:x} is not recognized", code));
+            let opcode = match opcodes.get(&code) {
+                Some(opcode) => opcode,
+                None => {
+                    self.program_counter -= 1;
+                    self.crash(CrashReason::from_opcode(code));
+                }
+            };
+            if self.bus.cdl.is_some() {
+                self.log_code_data(opcode);
+            }
             if code == 0x00 {
+                if let Some(coverage) = self.coverage.as_mut() {
+                    coverage.record(opcode, false, false);
+                }
                 return;
             }
 
+            // decided by the opcode table, before the instruction moves X or Y
+            if opcode.pays_page_cross() && self.page_crossed(&opcode.mode) {
+                self.extra_cycles += 1;
+            }
             self.handle_control_flow_ops(opcode, code);
             self.handle_logic_ops(opcode, code);
             self.handle_register_ops(opcode, code);
@@ -264,23 +388,128 @@ impl CPU {
             self.handle_stack_ops(opcode, code);
             self.handle_other_ops(opcode, code);
 
+            let jumped = program_counter_state != self.program_counter;
             if program_counter_state == self.program_counter {
                 self.program_counter += (opcode.len - 1) as u16;
+            } else if code == 0x6c {
+                self.log_indirect_jump_target();
+            }
+            if let Some(coverage) = self.coverage.as_mut() {
+                // a page crossing is whatever extra cycle isn't the taken
+                // branch's own
+                let branch_cycle = (is_branch(code) && jumped) as u8;
+                coverage.record(opcode, self.extra_cycles > branch_cycle, jumped);
+            }
+            self.bus.tick(opcode.cycles + self.extra_cycles);
+            if let Some(err) = self.bus.take_bus_error() {
+                self.crash(CrashReason::BusError(err));
             }
             callback(self);
         }
     }
-    pub fn reset(&mut self) {
+    // Hardware interrupt, between two instructions: same stack frame as BRK
+    // but with the B flag clear.
+    fn interrupt(&mut self, vector: u16) {
+        self.bus.begin_instruction();
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.status;
+        flags.remove(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits());
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.program_counter = self.mem_read_u16(vector);
+        self.bus.tick(INTERRUPT_CYCLES);
+    }
+
+    fn crash(&self, reason: CrashReason) -> ! {
+        match write_crash_report(self, &reason, &self.crash_report_dir) {
+            Ok(path) => panic!("{} (crash report: {})", reason.describe(), path.display()),
+            Err(err) => panic!("{} (can't write crash report: {})", reason.describe(), err),
+        }
+    }
+
+    // Cold boot of the whole machine: bus, RAM and devices first, then the
+    // CPU comes up with cleared registers and interrupts disabled.
+    pub fn power_on(&mut self) {
+        self.bus.power_on();
+        self.power_on_registers();
+    }
+
+    fn power_on_registers(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
         self.status = CpuFlags::from_bits_truncate(0b100100);
         self.stack_ptr = STACK_PTR_RESET;
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.history.clear();
+        self.bus.begin_instruction();
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+        self.bus.tick(RESET_CYCLES);
+    }
+
+    // Reset button: A, X, Y and RAM survive, the reset sequence runs three
+    // fake stack pushes (SP -= 3) and sets the interrupt disable flag.
+    pub fn reset(&mut self) {
+        self.bus.reset();
+        self.stack_ptr = self.stack_ptr.wrapping_sub(3);
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.bus.begin_instruction();
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+        self.bus.tick(RESET_CYCLES);
     }
     fn load_and_run(&mut self, program: Vec<u8>) {
         self.load(program);
-        self.reset();
+        self.power_on_registers();
         self.run();
     }
 }
+
+fn resolve_operand_address<F>(mode: &AddressingMode, pc: u16, x: u8, y: u8, mut read: F) -> u16
+where
+    F: FnMut(u16) -> u8,
+{
+    match mode {
+        AddressingMode::Immediate => pc,
+        AddressingMode::ZeroPage => read(pc) as u16,
+        AddressingMode::Absolute => {
+            let lo = read(pc) as u16;
+            let hi = read(pc.wrapping_add(1)) as u16;
+            hi << 8 | lo
+        }
+        AddressingMode::ZeroPage_X => {
+            let pos = read(pc);
+            pos.wrapping_add(x) as u16
+        }
+        AddressingMode::ZeroPage_Y => {
+            let pos = read(pc);
+            pos.wrapping_add(y) as u16
+        }
+
+        AddressingMode::Absolute_X => {
+            let base = resolve_operand_address(&AddressingMode::Absolute, pc, x, y, read);
+            base.wrapping_add(x as u16)
+        }
+        AddressingMode::Absolute_Y => {
+            let base = resolve_operand_address(&AddressingMode::Absolute, pc, x, y, read);
+            base.wrapping_add(y as u16)
+        }
+        AddressingMode::Indirect_X => {
+            let base = read(pc);
+            let ptr: u8 = base.wrapping_add(x);
+            let lowest = read(ptr as u16);
+            let highest = read(ptr.wrapping_add(1) as u16);
+            (highest as u16) << 8 | (lowest as u16)
+        }
+        AddressingMode::Indirect_Y => {
+            let base = read(pc);
+
+            let lowest = read(base as u16);
+            let highest = read(base.wrapping_add(1) as u16);
+            let deref_base = (highest as u16) << 8 | (lowest as u16);
+            deref_base.wrapping_add(y as u16)
+        }
+        AddressingMode::NoneAddressing => {
+            panic!("mode {:?} is not supported", mode)
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    cpu::{CpuFlags, CPU},
+    mem::Mem,
+    scheduler::EventKind,
+};
+
+#[test]
+fn test_power_on_state() {
+    let bus = Bus::new(gen_test_rom_with_program(&[]));
+    let mut cpu = CPU::new(bus);
+    cpu.register_a = 0x12;
+    cpu.mem_write(0x0010, 0x34);
+    cpu.power_on();
+
+    assert_eq!(cpu.register_a, 0);
+    assert_eq!(cpu.stack_ptr, 0xFD);
+    assert_eq!(cpu.status.bits(), 0x24);
+    assert_eq!(cpu.program_counter, 0x8000);
+    assert_eq!(cpu.mem_read(0x0010), 0);
+}
+
+#[test]
+fn test_reset_keeps_registers_and_ram() {
+    let bus = Bus::new(gen_test_rom_with_program(&[]));
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    cpu.register_a = 0x12;
+    cpu.register_x = 0x34;
+    cpu.register_y = 0x56;
+    cpu.status = CpuFlags::from_bits_truncate(0b0010_0001);
+    cpu.program_counter = 0x1234;
+    cpu.mem_write(0x0010, 0x78);
+
+    cpu.reset();
+    assert_eq!(cpu.register_a, 0x12);
+    assert_eq!(cpu.register_x, 0x34);
+    assert_eq!(cpu.register_y, 0x56);
+    assert_eq!(cpu.stack_ptr, 0xFA);
+    assert!(cpu.status.contains(CpuFlags::CARRY));
+    assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+    assert_eq!(cpu.program_counter, 0x8000);
+    assert_eq!(cpu.mem_read(0x0010), 0x78);
+}
+
+// main loop at $8000 waiting on NMI, handler at $8008
+fn gen_nmi_rom(enable_nmi: bool) -> Bus {
+    let ctrl = if enable_nmi { 0x80 } else { 0x00 };
+    let mut rom = gen_test_rom_with_program(&[
+        0xa9, ctrl, // LDA #ctrl
+        0x8d, 0x00, 0x20, // STA $2000
+        0x4c, 0x05, 0x80, // JMP $8005
+        0xa9, 0x42, // LDA #$42
+        0x85, 0x10, // STA $10
+        0x00, // BRK
+    ]);
+    rom.prg_rom[0x7FFA] = 0x08;
+    rom.prg_rom[0x7FFB] = 0x80;
+    Bus::new(rom)
+}
+
+#[test]
+fn test_vblank_nmi() {
+    let mut cpu = CPU::new(gen_nmi_rom(true));
+    cpu.power_on();
+    cpu.status.insert(CpuFlags::CARRY);
+    cpu.run_with_cb(|_| {});
+
+    assert_eq!(cpu.mem_read(0x10), 0x42);
+    assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+    assert_eq!(cpu.stack_ptr, 0xFA);
+    // return address and flags with B clear
+    assert_eq!(cpu.mem_read(0x01FD), 0x80);
+    assert_eq!(cpu.mem_read(0x01FC), 0x05);
+    assert_eq!(cpu.mem_read(0x01FB), 0b1010_0101);
+    // NMI comes with vblank, line 241 of the first frame
+    assert!(cpu.bus.cycles() > 241 * 341 / 3);
+    assert!(cpu.bus.cycles() < 242 * 341 / 3 + 20);
+}
+
+#[test]
+fn test_no_nmi_when_disabled() {
+    let mut cpu = CPU::new(gen_nmi_rom(false));
+    cpu.power_on();
+    let mut frames = 0;
+    cpu.run_with_cb(|cpu| {
+        if cpu.bus.poll_frame() {
+            frames += 1;
+            if frames == 3 {
+                cpu.program_counter = 0x800C;
+            }
+        }
+    });
+    assert_eq!(cpu.mem_read(0x10), 0);
+}
+
+#[test]
+fn test_mapper_irq() {
+    let mut rom = gen_test_rom_with_program(&[
+        0x58, // CLI
+        0x4c, 0x01, 0x80, // JMP $8001
+        0xe6, 0x10, // INC $10
+        0x00, // BRK
+    ]);
+    rom.prg_rom[0x7FFE] = 0x04;
+    rom.prg_rom[0x7FFF] = 0x80;
+    let mut cpu = CPU::new(Bus::new(rom));
+    cpu.power_on();
+    cpu.bus.clock.schedule_in(100, EventKind::MapperIrq);
+    cpu.run_with_cb(|_| {});
+
+    assert_eq!(cpu.mem_read(0x10), 1);
+    assert_eq!(cpu.mem_read(0x01FB) & 0x04, 0);
+    assert!(!cpu.bus.mapper_irq_pending());
+}
+
+#[test]
+fn test_branch_and_page_cross_cycles() {
+    let mut rom = gen_test_rom_with_program(&[
+        0xa2, 0x01, // LDX #$01
+        0xbd, 0xff, 0x80, // LDA $80FF,X (page cross)
+        0xbd, 0x00, 0x80, // LDA $8000,X
+        0x9d, 0x00, 0x02, // STA $0200,X
+        0xe0, 0x01, // CPX #$01
+        0xd0, 0x00, // BNE +0 (not taken)
+        0xf0, 0x00, // BEQ +0 (taken)
+        0x4c, 0xfc, 0x80, // JMP $80FC
+    ]);
+    // BEQ +2 at $80FC lands on $8100
+    rom.prg_rom[0xFC] = 0xf0;
+    rom.prg_rom[0xFD] = 0x02;
+    let mut cpu = CPU::new(Bus::new(rom));
+    cpu.power_on();
+    let mut last = cpu.bus.cycles();
+    let mut taken = vec![];
+    cpu.run_with_cb(|cpu| {
+        taken.push(cpu.bus.cycles() - last);
+        last = cpu.bus.cycles();
+    });
+    assert_eq!(taken, [2, 5, 4, 5, 2, 2, 3, 3, 4]);
+}
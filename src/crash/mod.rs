@@ -0,0 +1,161 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::cpu::CPU;
+use crate::mem::Mem;
+use crate::trace::disassemble;
+
+pub mod test;
+
+pub const HISTORY_SIZE: usize = 64;
+
+// KIL/JAM opcodes lock up a real 6502
+const JAM_OPCODES: [u8; 12] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrashReason {
+    InvalidOpcode(u8),
+    Jam(u8),
+    BusError(String),
+}
+
+impl CrashReason {
+    pub fn from_opcode(code: u8) -> Self {
+        if JAM_OPCODES.contains(&code) {
+            CrashReason::Jam(code)
+        } else {
+            CrashReason::InvalidOpcode(code)
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            CrashReason::InvalidOpcode(code) => format!("OpCode {:x} is not recognized", code),
+            CrashReason::Jam(code) => format!("CPU jammed on opcode {:x}", code),
+            CrashReason::BusError(msg) => format!("bus error: {}", msg),
+        }
+    }
+}
+
+// Fixed ring of the last executed instruction addresses, cheap enough to
+// stay on all the time.
+pub struct InstructionHistory {
+    pcs: [u16; HISTORY_SIZE],
+    next: usize,
+    len: usize,
+}
+
+impl Default for InstructionHistory {
+    fn default() -> Self {
+        InstructionHistory {
+            pcs: [0; HISTORY_SIZE],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl InstructionHistory {
+    #[inline]
+    pub fn push(&mut self, pc: u16) {
+        self.pcs[self.next] = pc;
+        self.next = (self.next + 1) % HISTORY_SIZE;
+        if self.len < HISTORY_SIZE {
+            self.len += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    // oldest first
+    pub fn entries(&self) -> Vec<u16> {
+        let start = (self.next + HISTORY_SIZE - self.len) % HISTORY_SIZE;
+        (0..self.len)
+            .map(|i| self.pcs[(start + i) % HISTORY_SIZE])
+            .collect()
+    }
+}
+
+fn hex_dump(cpu: &CPU, start: u16, len: u16) -> String {
+    let mut out = String::new();
+    for row in (0..len).step_by(16) {
+        let bytes: Vec<String> = (0..16)
+            .map(|i| format!("{:02X}", cpu.peek(start + row + i)))
+            .collect();
+        out.push_str(&format!("{:04X}: {}\n", start + row, bytes.join(" ")));
+    }
+    out
+}
+
+pub fn crash_report(cpu: &CPU, reason: &CrashReason) -> String {
+    let mut out = String::new();
+    out.push_str("rusty-nes crash report\n");
+    out.push_str(&format!("reason: {}\n\n", reason.describe()));
+
+    let symbols = cpu.symbols.as_ref();
+    let label = |addr: u16| symbols.and_then(|s| s.label(addr, cpu.bus.prg_rom_offset(addr)));
+
+    let pc = cpu.program_counter;
+    let pc_label = label(pc).map(|l| format!(" <{}>", l)).unwrap_or_default();
+    out.push_str("registers:\n");
+    out.push_str(&format!(
+        "  PC:{:04X}{} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}\n\n",
+        pc,
+        pc_label,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status.bits(),
+        cpu.stack_ptr
+    ));
+
+    out.push_str(&format!(
+        "last {} instructions (oldest first):\n",
+        cpu.history.len()
+    ));
+    for pc in cpu.history.entries() {
+        let dis = disassemble(&cpu.bus, pc, symbols);
+        let hex: Vec<String> = dis.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        out.push_str(&format!(
+            "  {:04X}  {:8}  {}{}\n",
+            pc,
+            hex.join(" "),
+            label(pc).map(|l| format!("{}: ", l)).unwrap_or_default(),
+            dis.text
+        ));
+    }
+
+    out.push_str(&format!("\nmapper:\n  {}\n", cpu.bus.mapper_banks()));
+    out.push_str("\nzero page:\n");
+    out.push_str(&hex_dump(cpu, 0x0000, 0x100));
+    out.push_str("\nstack page:\n");
+    out.push_str(&hex_dump(cpu, 0x0100, 0x100));
+    out
+}
+
+pub fn write_crash_report(cpu: &CPU, reason: &CrashReason, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!("crash-{}.txt", now));
+    fs::write(&path, crash_report(cpu, reason))?;
+    Ok(path)
+}
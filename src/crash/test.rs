@@ -0,0 +1,99 @@
+use std::{fs, panic};
+
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    cpu::CPU,
+    crash::{crash_report, CrashReason, InstructionHistory, HISTORY_SIZE},
+    symbols::{SymbolAddr, SymbolTable},
+};
+
+#[test]
+fn test_history_wraps() {
+    let mut history = InstructionHistory::default();
+    assert!(history.is_empty());
+    for pc in 0..(HISTORY_SIZE as u16 + 3) {
+        history.push(pc);
+    }
+    let entries = history.entries();
+    assert_eq!(entries.len(), HISTORY_SIZE);
+    assert_eq!(entries[0], 3);
+    assert_eq!(*entries.last().unwrap(), HISTORY_SIZE as u16 + 2);
+}
+
+#[test]
+fn test_report_content() {
+    // LDA #$42 ; STA $10 ; KIL
+    let bus = Bus::new(gen_test_rom_with_program(&[0xa9, 0x42, 0x85, 0x10, 0x02]));
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    cpu.history.push(0x8000);
+    cpu.history.push(0x8002);
+    cpu.register_a = 0x42;
+
+    let report = crash_report(&cpu, &CrashReason::from_opcode(0x02));
+    assert!(report.contains("reason: CPU jammed on opcode 2"));
+    assert!(report.contains("PC:8000 A:42"));
+    assert!(report.contains("  8000  A9 42     LDA #$42\n  8002  85 10     STA $10\n"));
+    assert!(report.contains("mapper:\n  mapper 0: $8000-$FFFF = PRG $00000-$07FFF\n"));
+    assert!(report.contains("zero page:\n0000: 00"));
+    assert!(report.contains("stack page:\n0100: 00"));
+}
+
+#[test]
+fn test_report_uses_symbols() {
+    // reset: LDA #$42 ; STA $10 ; KIL
+    let bus = Bus::new(gen_test_rom_with_program(&[0xa9, 0x42, 0x85, 0x10, 0x02]));
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+    let mut symbols = SymbolTable::new();
+    symbols.add("reset", SymbolAddr::PrgRom(0x0000), None);
+    symbols.add("counter", SymbolAddr::Cpu(0x0010), None);
+    cpu.symbols = Some(symbols);
+    cpu.history.push(0x8000);
+    cpu.history.push(0x8002);
+
+    let report = crash_report(&cpu, &CrashReason::from_opcode(0x02));
+    assert!(report.contains("PC:8000 <reset> A:00"));
+    assert!(report.contains("  8000  A9 42     reset: LDA #$42\n  8002  85 10     STA counter\n"));
+}
+
+#[test]
+fn test_invalid_opcode_writes_report() {
+    let dir = std::env::temp_dir().join(format!("rusty-nes-crash-{}", std::process::id()));
+    // LDX #$07 ; .byte $FF
+    let bus = Bus::new(gen_test_rom_with_program(&[0xa2, 0x07, 0xff]));
+    let mut cpu = CPU::new(bus);
+    cpu.crash_report_dir = dir.clone();
+    cpu.power_on();
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| cpu.run_with_cb(|_| {})));
+    assert!(result.is_err());
+
+    let report = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let content = fs::read_to_string(&report).unwrap();
+    assert!(content.contains("reason: OpCode ff is not recognized"));
+    assert!(content.contains("PC:8002 A:00 X:07"));
+    assert!(content.contains("8000  A2 07     LDX #$07"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_bus_error_writes_report() {
+    let dir = std::env::temp_dir().join(format!("rusty-nes-bus-error-{}", std::process::id()));
+    // LDA $5000
+    let mut bus = Bus::new(gen_test_rom_with_program(&[0xad, 0x00, 0x50]));
+    bus.fatal_unmapped = true;
+    let mut cpu = CPU::new(bus);
+    cpu.crash_report_dir = dir.clone();
+    cpu.power_on();
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| cpu.run_with_cb(|_| {})));
+    assert!(result.is_err());
+
+    let report = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let content = fs::read_to_string(&report).unwrap();
+    assert!(content.contains("reason: bus error: read from unmapped $5000"));
+    assert!(content.contains("8000  AD 00 50  LDA $5000"));
+    fs::remove_dir_all(&dir).unwrap();
+}
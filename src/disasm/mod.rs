@@ -0,0 +1,410 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::cartridge::Rom;
+use crate::cdl::{CodeDataLogger, PrgFlags};
+use crate::cpu::AddressingMode;
+use crate::opcodes::{self, OpCode};
+use crate::trace::{branch_target, format_instruction, is_branch};
+
+pub mod test;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ByteKind {
+    Unknown,
+    Opcode,
+    Operand,
+    Data,
+}
+
+// A line of the listing: the PRG bytes it stands for, so the listing can be
+// checked against the ROM, and its ca65 text.
+pub struct Line {
+    pub offset: usize,
+    pub code: bool,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+pub struct Listing {
+    pub header: Vec<u8>,
+    pub trainer: Vec<u8>,
+    pub prg: Vec<Line>,
+    pub chr: Vec<u8>,
+    pub base: u16,
+}
+
+struct Disassembler<'a> {
+    prg: &'a [u8],
+    base: u16,
+    kinds: Vec<ByteKind>,
+    owner: Vec<Option<usize>>,
+    labels: HashMap<usize, String>,
+    vectors: usize,
+}
+
+impl<'a> Disassembler<'a> {
+    fn offset(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+        // 16K images are mirrored at $8000 and $C000
+        let offset = (addr as usize - 0x8000) % self.prg.len();
+        Some(offset)
+    }
+
+    fn addr(&self, offset: usize) -> u16 {
+        self.base.wrapping_add(offset as u16)
+    }
+
+    fn read_vector(&self, vector: u16) -> u16 {
+        let offset = self.offset(vector).unwrap();
+        (self.prg[offset + 1] as u16) << 8 | self.prg[offset] as u16
+    }
+
+    fn add_label(&mut self, offset: usize, name: String) {
+        self.labels.entry(offset).or_insert(name);
+    }
+
+    fn trace(&mut self, entries: Vec<usize>) {
+        let mut queue: VecDeque<usize> = entries.into();
+
+        while let Some(mut offset) = queue.pop_front() {
+            loop {
+                if self.kinds[offset] != ByteKind::Unknown {
+                    break;
+                }
+                let opcode: &OpCode = match opcodes::OPCODES_MAP.get(&self.prg[offset]) {
+                    Some(opcode) => opcode,
+                    None => break,
+                };
+                let len = opcode.len as usize;
+                if offset + len > self.prg.len()
+                    || (1..len).any(|i| self.kinds[offset + i] != ByteKind::Unknown)
+                {
+                    break;
+                }
+
+                self.kinds[offset] = ByteKind::Opcode;
+                for i in 0..len {
+                    self.owner[offset + i] = Some(offset);
+                    if i > 0 {
+                        self.kinds[offset + i] = ByteKind::Operand;
+                    }
+                }
+
+                let addr = self.addr(offset);
+                let word = || (self.prg[offset + 2] as u16) << 8 | self.prg[offset + 1] as u16;
+                let code = opcode.code;
+                if is_branch(code) {
+                    let target = branch_target(addr, self.prg[offset + 1]);
+                    if let Some(target) = self.offset(target) {
+                        queue.push_back(target);
+                    }
+                } else if code == 0x20 || code == 0x4c {
+                    if let Some(target) = self.offset(word()) {
+                        queue.push_back(target);
+                    }
+                }
+
+                // control never falls through these
+                if matches!(code, 0x4c | 0x6c | 0x60 | 0x40 | 0x00) {
+                    break;
+                }
+                offset += len;
+                if offset >= self.prg.len() {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn collect_labels(&mut self) {
+        let mut targets = Vec::new();
+        for offset in 0..self.prg.len() {
+            if self.kinds[offset] != ByteKind::Opcode {
+                continue;
+            }
+            let opcode = opcodes::OPCODES_MAP[&self.prg[offset]];
+            let addr = self.addr(offset);
+            let target = match opcode.mode {
+                _ if is_branch(opcode.code) => Some(branch_target(addr, self.prg[offset + 1])),
+                AddressingMode::Absolute
+                | AddressingMode::Absolute_X
+                | AddressingMode::Absolute_Y => {
+                    Some((self.prg[offset + 2] as u16) << 8 | self.prg[offset + 1] as u16)
+                }
+                AddressingMode::NoneAddressing if opcode.len == 3 => {
+                    Some((self.prg[offset + 2] as u16) << 8 | self.prg[offset + 1] as u16)
+                }
+                _ => None,
+            };
+            if let Some(target) = target.and_then(|t| self.offset(t)) {
+                targets.push(target);
+            }
+        }
+
+        for target in targets {
+            // a label can't sit inside an instruction, those get referenced
+            // as an offset from the instruction start
+            let labeled = self.owner[target].unwrap_or(target);
+            let prefix = match self.kinds[labeled] {
+                ByteKind::Opcode => "L",
+                _ => "D",
+            };
+            let name = format!("{}_{:04X}", prefix, self.addr(labeled));
+            self.add_label(labeled, name);
+        }
+    }
+
+    fn name_for(&self, addr: u16) -> Option<String> {
+        let offset = self.offset(addr)?;
+        // mirrored 16K images: only the half the code is assembled at
+        if addr < self.base {
+            return None;
+        }
+        let labeled = self.owner[offset].unwrap_or(offset);
+        let name = self.labels.get(&labeled)?;
+        if labeled == offset {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+{}", name, offset - labeled))
+        }
+    }
+
+    // Start of one of the NMI/reset/IRQ words, listed one .word at a time so
+    // a label pointing at any of them (JMP ($FFFC)) gets defined. A label on
+    // the high byte leaves the word to .byte.
+    fn is_vector(&self, offset: usize) -> bool {
+        offset >= self.vectors
+            && (offset - self.vectors).is_multiple_of(2)
+            && self.kinds[offset..offset + 2]
+                .iter()
+                .all(|k| *k == ByteKind::Data)
+            && !self.labels.contains_key(&(offset + 1))
+    }
+
+    fn listing(&self) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < self.prg.len() {
+            if let Some(label) = self.labels.get(&offset) {
+                lines.push(Line {
+                    offset,
+                    code: false,
+                    bytes: vec![],
+                    text: format!("{}:", label),
+                });
+            }
+
+            if self.kinds[offset] == ByteKind::Opcode {
+                let opcode = opcodes::OPCODES_MAP[&self.prg[offset]];
+                let len = opcode.len as usize;
+                let bytes = self.prg[offset..offset + len].to_vec();
+                let force_absolute = matches!(
+                    opcode.mode,
+                    AddressingMode::Absolute
+                        | AddressingMode::Absolute_X
+                        | AddressingMode::Absolute_Y
+                );
+                let text = format_instruction(opcode, self.addr(offset), &bytes[1..], |target| {
+                    if force_absolute && target < 0x100 {
+                        // keep ca65 from shrinking it to zero page
+                        return Some(format!("a:${:04X}", target));
+                    }
+                    self.name_for(target)
+                });
+                lines.push(Line {
+                    offset,
+                    code: true,
+                    bytes,
+                    text: format!("\t{}", text),
+                });
+                offset += len;
+                continue;
+            }
+
+            if self.is_vector(offset) {
+                let target = self.read_vector(self.addr(offset));
+                let name = self
+                    .name_for(target)
+                    .unwrap_or_else(|| format!("${:04X}", target));
+                lines.push(Line {
+                    offset,
+                    code: false,
+                    bytes: self.prg[offset..offset + 2].to_vec(),
+                    text: format!("\t.word {}", name),
+                });
+                offset += 2;
+                continue;
+            }
+
+            let start = offset;
+            offset += 1;
+            while offset < self.prg.len()
+                && offset - start < 16
+                && self.kinds[offset] != ByteKind::Opcode
+                && !self.labels.contains_key(&offset)
+                && !self.is_vector(offset)
+            {
+                offset += 1;
+            }
+            let bytes = self.prg[start..offset].to_vec();
+            lines.push(Line {
+                offset: start,
+                code: false,
+                text: format!("\t.byte {}", hex_bytes(&bytes)),
+                bytes,
+            });
+        }
+        lines
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("${:02X}", b))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// Walks PRG-ROM from the interrupt vectors (and from every code run the CDL
+// saw executed, if one is given) and splits it into code and data.
+pub fn disassemble_rom(raw: &Vec<u8>, cdl: Option<&CodeDataLogger>) -> Result<Listing, String> {
+    let rom = Rom::new(raw)?;
+    let prg = &rom.prg_rom;
+    if prg.len() != 0x4000 && prg.len() != 0x8000 {
+        return Err("only 16K and 32K PRG-ROM layouts can be disassembled".to_string());
+    }
+
+    let mut dis = Disassembler {
+        prg,
+        base: (0x10000 - prg.len()) as u16,
+        kinds: vec![ByteKind::Unknown; prg.len()],
+        owner: vec![None; prg.len()],
+        labels: HashMap::new(),
+        vectors: prg.len() - 6,
+    };
+
+    let mut entries = Vec::new();
+    for (vector, name) in [
+        (RESET_VECTOR, "reset"),
+        (NMI_VECTOR, "nmi"),
+        (IRQ_VECTOR, "irq"),
+    ] {
+        let vector_offset = dis.offset(vector).unwrap();
+        dis.kinds[vector_offset] = ByteKind::Data;
+        dis.kinds[vector_offset + 1] = ByteKind::Data;
+        if let Some(target) = dis.offset(dis.read_vector(vector)) {
+            entries.push(target);
+            dis.add_label(target, name.to_string());
+        }
+    }
+    dis.add_label(dis.vectors, "vectors".to_string());
+
+    if let Some(cdl) = cdl {
+        if cdl.prg.len() != prg.len() {
+            return Err("cdl does not match the rom PRG size".to_string());
+        }
+        let mut previous_code = false;
+        for offset in 0..prg.len() {
+            let flags = cdl.prg_flags(offset);
+            let code = flags.contains(PrgFlags::CODE);
+            if code && !previous_code {
+                entries.push(offset);
+            }
+            if !code && flags.contains(PrgFlags::DATA) {
+                dis.kinds[offset] = ByteKind::Data;
+            }
+            previous_code = code;
+        }
+    }
+
+    dis.trace(entries);
+    dis.collect_labels();
+
+    let header_len = 16;
+    let trainer_len = if raw[6] & 0b100 != 0 { 512 } else { 0 };
+    let chr_start = header_len + trainer_len + prg.len();
+    Ok(Listing {
+        header: raw[..header_len].to_vec(),
+        trainer: raw[header_len..header_len + trainer_len].to_vec(),
+        prg: dis.listing(),
+        chr: raw[chr_start..chr_start + rom.chr_rom.len()].to_vec(),
+        base: dis.base,
+    })
+}
+
+impl Listing {
+    pub fn code_bytes(&self) -> usize {
+        self.prg
+            .iter()
+            .filter(|l| l.code)
+            .map(|l| l.bytes.len())
+            .sum()
+    }
+
+    pub fn to_ca65(&self) -> String {
+        let mut out = String::new();
+        out.push_str("; Generated by rusty-nes, assemble with the matching ld65 config\n\n");
+
+        out.push_str(".segment \"HEADER\"\n");
+        for chunk in self.header.chunks(16) {
+            out.push_str(&format!("\t.byte {}\n", hex_bytes(chunk)));
+        }
+        if !self.trainer.is_empty() {
+            out.push_str("\n.segment \"TRAINER\"\n");
+            for chunk in self.trainer.chunks(16) {
+                out.push_str(&format!("\t.byte {}\n", hex_bytes(chunk)));
+            }
+        }
+
+        out.push_str("\n.segment \"PRG\"\n");
+        for line in &self.prg {
+            out.push_str(&line.text);
+            out.push('\n');
+        }
+
+        if !self.chr.is_empty() {
+            out.push_str("\n.segment \"CHR\"\n");
+            for chunk in self.chr.chunks(16) {
+                out.push_str(&format!("\t.byte {}\n", hex_bytes(chunk)));
+            }
+        }
+        out
+    }
+
+    pub fn ld65_config(&self) -> String {
+        let prg_size = 0x10000 - self.base as usize;
+        let mut cfg = String::from("MEMORY {\n");
+        cfg.push_str("    HEADER:  start = $0000, size = $0010, file = %O, fill = yes;\n");
+        if !self.trainer.is_empty() {
+            cfg.push_str("    TRAINER: start = $7000, size = $0200, file = %O, fill = yes;\n");
+        }
+        cfg.push_str(&format!(
+            "    PRG:     start = ${:04X}, size = ${:04X}, file = %O, fill = yes;\n",
+            self.base, prg_size
+        ));
+        if !self.chr.is_empty() {
+            cfg.push_str(&format!(
+                "    CHR:     start = $0000, size = ${:04X}, file = %O, fill = yes;\n",
+                self.chr.len()
+            ));
+        }
+        cfg.push_str("}\nSEGMENTS {\n");
+        cfg.push_str("    HEADER:  load = HEADER,  type = ro;\n");
+        if !self.trainer.is_empty() {
+            cfg.push_str("    TRAINER: load = TRAINER, type = ro;\n");
+        }
+        cfg.push_str("    PRG:     load = PRG,     type = ro;\n");
+        if !self.chr.is_empty() {
+            cfg.push_str("    CHR:     load = CHR,     type = ro;\n");
+        }
+        cfg.push_str("}\n");
+        cfg
+    }
+}
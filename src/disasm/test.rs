@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::{
+    cdl::CodeDataLogger,
+    cpu::AddressingMode,
+    disasm::{disassemble_rom, Listing},
+    opcodes::{self, OpCode},
+    trace::is_branch,
+};
+
+fn gen_raw_rom(program: &[u8], data: &[(usize, u8)]) -> Vec<u8> {
+    let mut raw = vec![
+        0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+    ];
+    let mut prg = vec![0xff; 0x8000];
+    prg[..program.len()].copy_from_slice(program);
+    for (offset, value) in data {
+        prg[*offset] = *value;
+    }
+    // nmi = $8010, reset = $8000, irq = $8010
+    prg[0x7FFA..].copy_from_slice(&[0x10, 0x80, 0x00, 0x80, 0x10, 0x80]);
+    raw.extend(prg);
+    raw.extend(vec![0x55; 0x2000]);
+    raw
+}
+
+// "$12", "a:$0012", "label" or "label+2", and whether it takes two bytes.
+// Without labels only the size is worked out, they are all in PRG-ROM.
+fn operand_value(expr: &str, labels: Option<&HashMap<String, u16>>) -> (u16, bool) {
+    if let Some(expr) = expr.strip_prefix("a:") {
+        return (operand_value(expr, labels).0, true);
+    }
+    if let Some(hex) = expr.strip_prefix('$') {
+        return (u16::from_str_radix(hex, 16).unwrap(), hex.len() > 2);
+    }
+    let (name, add) = match expr.split_once('+') {
+        Some((name, add)) => (name, add.parse::<u16>().unwrap()),
+        None => (expr, 0),
+    };
+    let value = match labels {
+        Some(labels) => *labels
+            .get(name)
+            .unwrap_or_else(|| panic!("undefined label {}", name)),
+        None => 0x8000,
+    };
+    (value.wrapping_add(add), true)
+}
+
+fn find_opcode(mnemonic: &str, mode: AddressingMode, code: Option<u8>) -> &'static OpCode {
+    opcodes::OPCODES_MAP
+        .values()
+        .copied()
+        .find(|op| op.human == mnemonic && op.mode == mode && code.is_none_or(|c| op.code == c))
+        .unwrap_or_else(|| panic!("no {} in {:?}", mnemonic, mode))
+}
+
+// Assembles one line of the disassembler's ca65 output at `pc`.
+fn assemble_line(line: &str, pc: u16, labels: Option<&HashMap<String, u16>>) -> Vec<u8> {
+    if let Some(list) = line.strip_prefix(".byte ") {
+        return list
+            .split(',')
+            .map(|b| u8::from_str_radix(b.trim_start_matches('$'), 16).unwrap())
+            .collect();
+    }
+    if let Some(list) = line.strip_prefix(".word ") {
+        return list
+            .split(',')
+            .flat_map(|w| operand_value(w, labels).0.to_le_bytes())
+            .collect();
+    }
+
+    let (mnemonic, operand) = line.split_once(' ').unwrap_or((line, ""));
+    let with = |op: &OpCode, value: u16| {
+        let mut bytes = vec![op.code];
+        bytes.extend(&value.to_le_bytes()[..op.len as usize - 1]);
+        bytes
+    };
+    if operand.is_empty() || operand == "A" {
+        let op = opcodes::OPCODES_MAP
+            .values()
+            .find(|op| op.human == mnemonic && op.len == 1)
+            .unwrap();
+        return vec![op.code];
+    }
+    if let Some(hex) = operand.strip_prefix("#$") {
+        let op = find_opcode(mnemonic, AddressingMode::Immediate, None);
+        return vec![op.code, u8::from_str_radix(hex, 16).unwrap()];
+    }
+    if let Some(expr) = operand.strip_prefix('(') {
+        let (expr, mode, code) = if let Some(expr) = expr.strip_suffix(",X)") {
+            (expr, AddressingMode::Indirect_X, None)
+        } else if let Some(expr) = expr.strip_suffix("),Y") {
+            (expr, AddressingMode::Indirect_Y, None)
+        } else {
+            (
+                expr.strip_suffix(')').unwrap(),
+                AddressingMode::NoneAddressing,
+                Some(0x6c),
+            )
+        };
+        return with(
+            find_opcode(mnemonic, mode, code),
+            operand_value(expr, labels).0,
+        );
+    }
+
+    let (expr, index) = match operand.split_once(',') {
+        Some((expr, index)) => (expr, Some(index)),
+        None => (operand, None),
+    };
+    let (value, wide) = operand_value(expr, labels);
+    let mode = match (index, wide) {
+        (Some("X"), true) => AddressingMode::Absolute_X,
+        (Some("X"), false) => AddressingMode::ZeroPage_X,
+        (Some("Y"), true) => AddressingMode::Absolute_Y,
+        (Some("Y"), false) => AddressingMode::ZeroPage_Y,
+        (_, true) => AddressingMode::Absolute,
+        (_, false) => AddressingMode::ZeroPage,
+    };
+    if let Some(op) = opcodes::OPCODES_MAP
+        .values()
+        .find(|op| op.human == mnemonic && is_branch(op.code))
+    {
+        let offset = value.wrapping_sub(pc.wrapping_add(2)) as i16;
+        assert!(
+            labels.is_none() || (-128..=127).contains(&offset),
+            "branch out of range: {}",
+            line
+        );
+        return vec![op.code, offset as u8];
+    }
+    if mnemonic == "JMP" || mnemonic == "JSR" {
+        let code = if mnemonic == "JMP" { 0x4c } else { 0x20 };
+        return with(
+            find_opcode(mnemonic, AddressingMode::NoneAddressing, Some(code)),
+            value,
+        );
+    }
+    with(find_opcode(mnemonic, mode, None), value)
+}
+
+// Assembles the PRG segment of the emitted source back into bytes, two
+// passes like ca65, so every label the text uses has to be defined in it.
+fn reassemble(listing: &Listing) -> Vec<u8> {
+    let source = listing.to_ca65();
+    let prg = source.split(".segment \"PRG\"\n").nth(1).unwrap();
+    let prg = prg.split("\n.segment").next().unwrap();
+    let lines: Vec<&str> = prg.lines().filter(|l| !l.is_empty()).collect();
+
+    let mut labels = HashMap::new();
+    let mut len = 0usize;
+    for line in &lines {
+        match line.strip_suffix(':') {
+            Some(label) => {
+                labels.insert(label.to_string(), listing.base.wrapping_add(len as u16));
+            }
+            None => len += assemble_line(line.trim(), 0, None).len(),
+        }
+    }
+
+    let mut prg = Vec::new();
+    for line in lines.iter().filter(|l| !l.ends_with(':')) {
+        let pc = listing.base.wrapping_add(prg.len() as u16);
+        prg.extend(assemble_line(line.trim(), pc, Some(&labels)));
+    }
+    prg
+}
+
+#[test]
+fn test_follows_vectors_and_branches() {
+    // reset: LDA $0080 ; BEQ +3 ; JSR $8014 ; JMP $8000
+    // nmi:   RTI
+    // sub:   LDA $8020,X ; RTS
+    let program = vec![
+        0xad, 0x80, 0x00, 0xf0, 0x03, 0x20, 0x14, 0x80, 0x4c, 0x00, 0x80, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x40, 0xff, 0xff, 0xff, 0xbd, 0x20, 0x80, 0x60,
+    ];
+    let raw = gen_raw_rom(&program, &[]);
+    let listing = disassemble_rom(&raw, None).unwrap();
+    let source = listing.to_ca65();
+
+    assert!(source
+        .contains("reset:\n\tLDA a:$0080\n\tBEQ L_8008\n\tJSR L_8014\nL_8008:\n\tJMP reset\n"));
+    assert!(source.contains("nmi:\n\tRTI\n"));
+    assert!(source.contains("L_8014:\n\tLDA D_8020,X\n\tRTS\n"));
+    assert!(source.contains("D_8020:\n\t.byte $FF"));
+    assert!(source.contains("vectors:\n\t.word nmi\n\t.word reset\n\t.word nmi\n"));
+    assert!(source.contains(".segment \"CHR\"\n\t.byte $55"));
+    assert_eq!(listing.code_bytes(), 16);
+
+    assert_eq!(reassemble(&listing), raw[16..16 + 0x8000].to_vec());
+    assert_eq!(listing.header, raw[..16].to_vec());
+    assert_eq!(listing.chr, raw[16 + 0x8000..].to_vec());
+}
+
+#[test]
+fn test_labels_inside_vectors() {
+    // reset: JMP ($FFFC)
+    let raw = gen_raw_rom(&[0x6c, 0xfc, 0xff], &[]);
+    let listing = disassemble_rom(&raw, None).unwrap();
+    let source = listing.to_ca65();
+
+    assert!(source.contains("reset:\n\tJMP (D_FFFC)\n"));
+    assert!(source.contains("vectors:\n\t.word nmi\nD_FFFC:\n\t.word reset\n\t.word nmi\n"));
+    assert_eq!(reassemble(&listing), raw[16..16 + 0x8000].to_vec());
+}
+
+#[test]
+fn test_cdl_seeds_code_and_data() {
+    // reset: JMP ($0300), the code at $8003 is only reachable indirectly
+    let program = vec![0x6c, 0x00, 0x03, 0xa9, 0x01, 0x60];
+    let raw = gen_raw_rom(&program, &[(0x0100, 0xa9)]);
+
+    let mut cdl = CodeDataLogger::new(0x8000, 0x2000);
+    for offset in 3..6 {
+        cdl.log_opcode(offset, 0x8000 + offset as u16);
+    }
+    // looks like LDA #imm but the game only ever reads it
+    cdl.log_data(0x0100, 0x8100);
+
+    let listing = disassemble_rom(&raw, Some(&cdl)).unwrap();
+    let source = listing.to_ca65();
+    assert!(source.contains("\tJMP ($0300)\n\tLDA #$01\n\tRTS\n"));
+    assert!(!source.contains("\tLDA #$FF"));
+    assert_eq!(reassemble(&listing), raw[16..16 + 0x8000].to_vec());
+}
+
+#[test]
+fn test_ld65_config() {
+    let raw = gen_raw_rom(&[0x4c, 0x00, 0x80], &[]);
+    let cfg = disassemble_rom(&raw, None).unwrap().ld65_config();
+    assert!(cfg.contains("PRG:     start = $8000, size = $8000"));
+    assert!(cfg.contains("CHR:     start = $0000, size = $2000"));
+}
@@ -1,12 +1,11 @@
+use std::path::Path;
+
 use rand::Rng;
-use sdl2::{
-    event::Event,
-    keyboard::Keycode,
-    pixels::{Color, PixelFormatEnum},
-    EventPump,
-};
+use sdl2::{event::Event, keyboard::Keycode, pixels::PixelFormatEnum, EventPump};
 
-use crate::{cpu::CPU, mem::Mem};
+use crate::{
+    cpu::CPU, mem::Mem, ppu::palette::Palette, scale::VideoFilter, screenshot::Screenshot,
+};
 
 lazy_static! {
     pub static ref SNAKE_GAME: Vec<u8> = vec![
@@ -34,13 +33,13 @@ lazy_static! {
     ];
 }
 
-pub fn load_and_run_snake(cpu: &mut CPU) {
+pub fn load_and_run_snake(cpu: &mut CPU, filter: &VideoFilter) {
+    cpu.power_on();
     cpu.load(SNAKE_GAME.to_vec());
-    cpu.reset();
-    run_snake(cpu);
+    run_snake(cpu, filter);
 }
 
-pub fn run_snake(cpu: &mut CPU) {
+pub fn run_snake(cpu: &mut CPU, filter: &VideoFilter) {
     let sdl_ctx = sdl2::init().unwrap();
     let video_sys = sdl_ctx.video().unwrap();
     let window = video_sys
@@ -51,21 +50,29 @@ pub fn run_snake(cpu: &mut CPU) {
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pmp = sdl_ctx.event_pump().unwrap();
 
-    canvas.set_scale(10.0, 10.0).unwrap();
-
+    // the texture is the filter output, stretched to the window
+    let (width, height) = filter.output_size(32, 32);
     let txt_creator = canvas.texture_creator();
     let mut txt = txt_creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
+        .create_texture_target(PixelFormatEnum::RGB24, width as u32, height as u32)
         .unwrap();
 
     let mut screen_state = [0 as u8; 32 * 3 * 32];
     let mut rng = rand::thread_rng();
+    let palette = Palette::default();
     cpu.run_with_cb(|cpu| {
-        handle_snake_input(cpu, &mut event_pmp);
+        if handle_snake_input(cpu, &mut event_pmp) {
+            let shot = Screenshot::from_rgb(screen_state.to_vec(), 32, 32).filtered(filter);
+            match shot.save_timestamped(Path::new("."), "snake") {
+                Ok(path) => println!("screenshot saved to {}", path.display()),
+                Err(e) => eprintln!("can't save screenshot: {}", e),
+            }
+        }
         cpu.mem_write(0xfe, rng.gen_range(1..16));
 
-        if read_screen_state_snake(cpu, &mut screen_state) {
-            txt.update(None, &screen_state, 32 * 3).unwrap();
+        if read_screen_state_snake(cpu, &palette, &mut screen_state) {
+            let scaled = filter.apply(&screen_state, 32, 32);
+            txt.update(None, &scaled, width * 3).unwrap();
             canvas.copy(&txt, None, None).unwrap();
             canvas.present();
         }
@@ -73,25 +80,27 @@ pub fn run_snake(cpu: &mut CPU) {
     })
 }
 
-fn bw_to_rgb(byte: u8) -> Color {
+// snake's color numbers as NES palette entries
+fn snake_color(byte: u8) -> u16 {
     match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+        0 => 0x0F,
+        1 => 0x30,
+        2 | 9 => 0x00,
+        3 | 10 => 0x16,
+        4 | 11 => 0x2A,
+        5 | 12 => 0x12,
+        6 | 13 => 0x24,
+        7 | 14 => 0x28,
+        _ => 0x2C,
     }
 }
-fn read_screen_state_snake(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
+
+fn read_screen_state_snake(cpu: &CPU, palette: &Palette, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     let mut frame_idx = 0;
     let mut upd = false;
     for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = bw_to_rgb(color_idx).rgb();
+        let color_idx = cpu.peek(i as u16);
+        let (b1, b2, b3) = palette.rgb(snake_color(color_idx));
 
         if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
             frame[frame_idx] = b1;
@@ -104,7 +113,9 @@ fn read_screen_state_snake(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     upd
 }
 
-fn handle_snake_input(cpu: &mut CPU, event_pmp: &mut EventPump) {
+// true when a screenshot was asked for (F12)
+fn handle_snake_input(cpu: &mut CPU, event_pmp: &mut EventPump) -> bool {
+    let mut screenshot = false;
     for event in event_pmp.poll_iter() {
         match event {
             Event::Quit { .. }
@@ -112,6 +123,10 @@ fn handle_snake_input(cpu: &mut CPU, event_pmp: &mut EventPump) {
                 keycode: Some(Keycode::Escape),
                 ..
             } => std::process::exit(0),
+            Event::KeyDown {
+                keycode: Some(Keycode::F12),
+                ..
+            } => screenshot = true,
             Event::KeyDown {
                 keycode: Some(Keycode::Z),
                 ..
@@ -139,4 +154,5 @@ fn handle_snake_input(cpu: &mut CPU, event_pmp: &mut EventPump) {
             _ => { /* do nothing */ }
         }
     }
+    screenshot
 }
@@ -3,13 +3,29 @@ use std::fs;
 use cartridge::Rom;
 use cpu::CPU;
 use games::{load_and_run_snake, run_snake, SNAKE_GAME};
+use scale::{Scaler, VideoFilter};
+use std::path::Path;
+use symbols::SymbolTable;
 
 pub mod bus;
 pub mod cartridge;
+pub mod cdl;
+pub mod coverage;
 pub mod cpu;
+pub mod crash;
+pub mod disasm;
 pub mod games;
 pub mod mem;
+pub mod ntsc;
 pub mod opcodes;
+pub mod png;
+pub mod ppu;
+pub mod region;
+pub mod scale;
+pub mod scheduler;
+pub mod screenshot;
+pub mod symbols;
+pub mod trace;
 
 #[macro_use]
 extern crate lazy_static;
@@ -19,7 +35,7 @@ extern crate bitflags;
 fn main() {
     // let bus = Bus::new(gen_test_rom());
     // let mut cpu: CPU = CPU::new();
-    // load_and_run_snake(&mut cpu);
+    // load_and_run_snake(&mut cpu, &VideoFilter::new(Scaler::Nearest(10)));
 
     Rom::create_fake_rom("roms/snake.nes".to_string(), SNAKE_GAME.to_vec());
     let bytes: Vec<u8> = std::fs::read("roms/snake_2.nes").unwrap();
@@ -27,6 +43,47 @@ fn main() {
 
     let bus = bus::Bus::new(rom);
     let mut cpu = CPU::new(bus);
-    cpu.reset();
-    run_snake(&mut cpu);
+    cpu.power_on();
+    let args: Vec<String> = std::env::args().collect();
+    let filter = match video_filter(&args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match symbol_table(&args) {
+        Ok(symbols) => cpu.symbols = symbols,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    run_snake(&mut cpu, &filter);
+}
+
+// --symbols <file> loads labels for crash reports, any number of times
+fn symbol_table(args: &[String]) -> Result<Option<SymbolTable>, String> {
+    let mut symbols = None;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--symbols" {
+            let path = args.get(i + 1).ok_or("--symbols needs a file")?;
+            symbols
+                .get_or_insert_with(SymbolTable::new)
+                .load_file(Path::new(path))?;
+        }
+    }
+    Ok(symbols)
+}
+
+// --scaler <name> picks the scaler, see Scaler::from_name
+fn video_filter(args: &[String]) -> Result<VideoFilter, String> {
+    let scaler = match args.iter().position(|arg| arg == "--scaler") {
+        Some(i) => {
+            let name = args.get(i + 1).ok_or("--scaler needs a name")?;
+            Scaler::from_name(name)?
+        }
+        None => Scaler::Nearest(10),
+    };
+    Ok(VideoFilter::new(scaler))
 }
@@ -1,13 +1,27 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub mod test;
+
+// mem_read is a real bus access and may change state (PPU status, read
+// buffers, controller shift registers). peek gives the same value without
+// touching anything, for debuggers, tracers and frontends.
 pub trait Mem {
-    fn mem_read(&self, addr: u16) -> u8;
+    fn mem_read(&mut self, addr: u16) -> u8;
     fn mem_write(&mut self, addr: u16, data: u8);
+    fn peek(&self, addr: u16) -> u8;
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos) as u16;
         let hi = self.mem_read(pos + 1) as u16;
         (hi << 8) | (lo as u16)
     }
 
+    fn peek_u16(&self, pos: u16) -> u16 {
+        let lo = self.peek(pos) as u16;
+        let hi = self.peek(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xff) as u8;
@@ -15,3 +29,42 @@ pub trait Mem {
         self.mem_write(pos + 1, hi);
     }
 }
+
+// What RAM holds right after power-on. Real consoles come up with
+// semi-random content, so anything but Zeros helps finding reads of
+// uninitialized variables.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    Ones,
+    Pattern(Vec<u8>),
+    Random(u64),
+}
+
+impl RamInit {
+    // regions are filled one after the other so a random seed still gives
+    // each of them different content
+    pub fn fill(&self, regions: &mut [&mut [u8]]) {
+        match self {
+            RamInit::Zeros => regions.iter_mut().for_each(|r| r.fill(0)),
+            RamInit::Ones => regions.iter_mut().for_each(|r| r.fill(0xFF)),
+            RamInit::Pattern(pattern) if pattern.is_empty() => {
+                regions.iter_mut().for_each(|r| r.fill(0))
+            }
+            RamInit::Pattern(pattern) => {
+                for region in regions.iter_mut() {
+                    for (i, byte) in region.iter_mut().enumerate() {
+                        *byte = pattern[i % pattern.len()];
+                    }
+                }
+            }
+            RamInit::Random(seed) => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                for region in regions.iter_mut() {
+                    rng.fill(&mut **region);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    mem::{Mem, RamInit},
+};
+
+#[test]
+fn test_fill_policies() {
+    let mut a = [0x11; 8];
+    let mut b = [0x22; 4];
+
+    RamInit::Zeros.fill(&mut [&mut a[..], &mut b[..]]);
+    assert_eq!(a, [0; 8]);
+    assert_eq!(b, [0; 4]);
+
+    RamInit::Ones.fill(&mut [&mut a[..], &mut b[..]]);
+    assert_eq!(a, [0xFF; 8]);
+
+    RamInit::Pattern(vec![0x00, 0x00, 0xFF, 0xFF]).fill(&mut [&mut a[..], &mut b[..]]);
+    assert_eq!(a, [0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF]);
+    assert_eq!(b, [0x00, 0x00, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_random_is_seeded() {
+    let mut first = [0; 64];
+    let mut second = [0; 64];
+    RamInit::Random(42).fill(&mut [&mut first[..]]);
+    RamInit::Random(42).fill(&mut [&mut second[..]]);
+    assert_eq!(first, second);
+    assert!(first.iter().any(|b| *b != 0));
+
+    RamInit::Random(43).fill(&mut [&mut second[..]]);
+    assert_ne!(first, second);
+}
+
+#[test]
+fn test_bus_power_on_applies_policy() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.ram_init = RamInit::Ones;
+    bus.power_on();
+    assert_eq!(bus.mem_read(0x0000), 0xFF);
+    assert_eq!(bus.mem_read(0x07FF), 0xFF);
+    assert_eq!(bus.mem_read(0x6000), 0xFF);
+    assert_eq!(bus.ppu().vram[0x07FF], 0xFF);
+    assert_eq!(bus.ppu().palette_table[0x1F], 0xFF);
+    assert_eq!(bus.ppu().oam_data[0xFF], 0xFF);
+
+    bus.mem_write(0x6000, 0x12);
+    bus.reset();
+    assert_eq!(bus.mem_read(0x6000), 0x12);
+}
@@ -0,0 +1,144 @@
+use std::f32::consts::PI;
+
+use crate::ppu::frame::{Frame, HEIGHT, WIDTH};
+
+pub mod test;
+
+// Composite video simulation after Bisqwit's decoder from the nesdev wiki
+// "NTSC video" page. Each PPU pixel becomes 8 samples of a square wave
+// whose phase is the hue and whose levels are the brightness, 12 samples
+// per color cycle. Decoding averages a 12 sample window around every
+// output pixel, which is where bleeding and the blending of alternating
+// columns come from, and the phase moving between frames is the dot crawl.
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const LINE_SAMPLES: usize = WIDTH * SAMPLES_PER_PIXEL;
+// 341 dots of 8 samples: every line starts 4 samples later in the cycle
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_PIXEL % SAMPLES_PER_CYCLE;
+
+// voltages relative to sync: 4 luma levels, low then high half of the wave
+const LEVELS: [f32; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+// lines up the decoded hues with the usual palettes, in samples
+const HUE_OFFSET: f32 = 3.9;
+
+// NTSC signal level of a pixel value (palette entry and emphasis bits) at
+// a sample phase, 0.0 black to 1.0 white
+fn signal(pixel: u16, phase: usize) -> f32 {
+    let color = (pixel & 0x0F) as usize;
+    let mut level = ((pixel >> 4) & 0x03) as usize;
+    let emphasis = (pixel >> 6) & 0x07;
+    // $xE/$xF are forced to the black level
+    if color > 13 {
+        level = 1;
+    }
+    let mut low = LEVELS[level];
+    let mut high = LEVELS[4 + level];
+    if color == 0 {
+        low = high;
+    }
+    if color > 12 {
+        high = low;
+    }
+
+    let in_phase = |color: usize| (color + phase) % SAMPLES_PER_CYCLE < 6;
+    let mut signal = if in_phase(color) { high } else { low };
+    // each emphasis bit attenuates the part of the cycle of its color
+    if (emphasis & 1 != 0 && in_phase(0))
+        || (emphasis & 2 != 0 && in_phase(4))
+        || (emphasis & 4 != 0 && in_phase(8))
+    {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+pub struct NtscFilter {
+    // output pixels per line, 256 up to 2048 (one per sample)
+    pub width: usize,
+    // extra hue rotation in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub gamma: f32,
+    // signal of every pixel value at each phase
+    levels: Vec<[f32; SAMPLES_PER_CYCLE]>,
+}
+
+impl NtscFilter {
+    pub fn new(width: usize) -> Self {
+        let levels = (0..512u16)
+            .map(|pixel| {
+                let mut levels = [0.0; SAMPLES_PER_CYCLE];
+                for (phase, level) in levels.iter_mut().enumerate() {
+                    *level = signal(pixel, phase);
+                }
+                levels
+            })
+            .collect();
+        NtscFilter {
+            width,
+            hue: 0.0,
+            saturation: 1.0,
+            gamma: 2.0,
+            levels,
+        }
+    }
+
+    // RGB24 frame of width x 240
+    pub fn apply(&self, frame: &Frame) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width * HEIGHT * 3);
+        // carrier for the I and Q demodulation, per phase
+        let hue = HUE_OFFSET + self.hue / 30.0;
+        let carrier: Vec<(f32, f32)> = (0..SAMPLES_PER_CYCLE)
+            .map(|p| {
+                let angle = PI * (p as f32 + hue) / 6.0;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+
+        let mut samples = vec![0.0; LINE_SAMPLES];
+        for y in 0..HEIGHT {
+            let line_phase = frame.phase as usize + y * LINE_PHASE_STEP;
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let pixel = frame.pixel(i / SAMPLES_PER_PIXEL, y) & 0x1FF;
+                *sample = self.levels[pixel as usize][(line_phase + i) % SAMPLES_PER_CYCLE];
+            }
+
+            for x in 0..self.width {
+                let center = x * LINE_SAMPLES / self.width;
+                let begin = center.saturating_sub(SAMPLES_PER_CYCLE / 2);
+                let end = (center + SAMPLES_PER_CYCLE / 2).min(LINE_SAMPLES);
+                let (mut luma, mut i, mut q) = (0.0, 0.0, 0.0);
+                for (p, sample) in samples.iter().enumerate().take(end).skip(begin) {
+                    let level = sample / SAMPLES_PER_CYCLE as f32;
+                    let (cos, sin) = carrier[(line_phase + p) % SAMPLES_PER_CYCLE];
+                    luma += level;
+                    i += level * cos;
+                    q += level * sin;
+                }
+                let (r, g, b) = self.yiq_to_rgb(luma, i * self.saturation, q * self.saturation);
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+        rgb
+    }
+
+    fn yiq_to_rgb(&self, y: f32, i: f32, q: f32) -> (u8, u8, u8) {
+        let channel = |value: f32| {
+            let value = if value <= 0.0 {
+                0.0
+            } else {
+                value.powf(2.2 / self.gamma)
+            };
+            (value * 255.95).clamp(0.0, 255.0) as u8
+        };
+        (
+            channel(y + 0.946882 * i + 0.623557 * q),
+            channel(y - 0.274788 * i - 0.635691 * q),
+            channel(y - 1.108545 * i + 1.709007 * q),
+        )
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    ntsc::NtscFilter,
+    ppu::frame::{Frame, HEIGHT, WIDTH},
+};
+
+fn solid_frame(pixel: u16) -> Frame {
+    let mut frame = Frame::new();
+    frame.pixels = vec![pixel; WIDTH * HEIGHT];
+    frame
+}
+
+// RGB of the output pixel x of line y
+fn rgb_at(rgb: &[u8], width: usize, x: usize, y: usize) -> (u8, u8, u8) {
+    let i = (y * width + x) * 3;
+    (rgb[i], rgb[i + 1], rgb[i + 2])
+}
+
+#[test]
+fn test_output_size() {
+    for width in [256, 602, 1024] {
+        let filter = NtscFilter::new(width);
+        assert_eq!(filter.apply(&Frame::new()).len(), width * HEIGHT * 3);
+    }
+}
+
+#[test]
+fn test_colors() {
+    let filter = NtscFilter::new(602);
+    let color = |pixel| rgb_at(&filter.apply(&solid_frame(pixel)), 602, 300, 100);
+
+    assert_eq!(color(0x0F), (0, 0, 0));
+    let (r, g, b) = color(0x30);
+    assert!(r > 250 && g > 250 && b > 250);
+    let (r, g, b) = color(0x16);
+    assert!(r > g && r > b);
+    let (r, g, b) = color(0x2A);
+    assert!(g > r && g > b);
+    let (r, g, b) = color(0x12);
+    assert!(b > r && b > g);
+}
+
+#[test]
+fn test_emphasis_darkens() {
+    let filter = NtscFilter::new(256);
+    let white = rgb_at(&filter.apply(&solid_frame(0x30)), 256, 100, 100);
+    let dimmed = rgb_at(
+        &filter.apply(&solid_frame(0x30 | 0b111 << 6)),
+        256,
+        100,
+        100,
+    );
+    assert!(dimmed.0 < white.0 && dimmed.1 < white.1 && dimmed.2 < white.2);
+}
+
+#[test]
+fn test_alternating_columns_blend() {
+    // 1 pixel wide stripes, as used for pseudo transparency
+    let mut frame = solid_frame(0x0F);
+    for y in 0..HEIGHT {
+        for x in (0..WIDTH).step_by(2) {
+            frame.set_pixel(x, y, 0x30);
+        }
+    }
+    let filter = NtscFilter::new(256);
+    let rgb = filter.apply(&frame);
+    let (r, g, b) = rgb_at(&rgb, 256, 101, 50);
+    assert!(r > 0 && g > 0 && b > 0);
+    let (r, g, b) = rgb_at(&rgb, 256, 100, 50);
+    assert!(r < 255 || g < 255 || b < 255);
+}
+
+#[test]
+fn test_dot_crawl() {
+    // a sharp edge bleeds color, differently as the phase moves
+    let mut frame = solid_frame(0x0F);
+    for y in 0..HEIGHT {
+        for x in 128..WIDTH {
+            frame.set_pixel(x, y, 0x30);
+        }
+    }
+    let filter = NtscFilter::new(256);
+    let first = filter.apply(&frame);
+    frame.phase = 4;
+    let second = filter.apply(&frame);
+    assert_ne!(first, second);
+    // lines alternate too
+    assert_ne!(rgb_at(&first, 256, 128, 0), rgb_at(&first, 256, 128, 1));
+    // away from the edge nothing changes
+    assert_eq!(rgb_at(&first, 256, 200, 0), rgb_at(&second, 256, 200, 0));
+}
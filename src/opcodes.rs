@@ -9,7 +9,30 @@ pub struct OpCode {
     pub mode: AddressingMode,
 }
 
+// instructions writing to their operand address: stores and
+// read-modify-write
+const WRITE_MNEMONICS: [&str; 9] = [
+    "STA", "STX", "STY", "ASL", "LSR", "ROL", "ROR", "INC", "DEC",
+];
+
 impl OpCode {
+    pub fn writes_operand(&self) -> bool {
+        WRITE_MNEMONICS.contains(&self.human)
+    }
+
+    // Instructions that only read their operand pay 1 more cycle when the
+    // indexed address crosses a page. Writes always take the long path,
+    // already in their base count.
+    pub fn pays_page_cross(&self) -> bool {
+        !self.writes_operand()
+            && matches!(
+                self.mode,
+                AddressingMode::Absolute_X
+                    | AddressingMode::Absolute_Y
+                    | AddressingMode::Indirect_Y
+            )
+    }
+
     fn new(code: u8, human: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
         OpCode {
             code: code,
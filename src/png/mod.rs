@@ -0,0 +1,105 @@
+use std::{fs, io, path::Path};
+
+pub mod test;
+
+// Minimal PNG writer for screenshots and debug views. The image data goes
+// out in stored (uncompressed) deflate blocks: files are big but any
+// viewer opens them and we need no extra dependency.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+// largest payload of a stored deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+pub fn encode_rgb(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    encode(width, height, rgb, 3, COLOR_TYPE_RGB)
+}
+
+pub fn encode_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    encode(width, height, rgba, 4, COLOR_TYPE_RGBA)
+}
+
+pub fn save_rgb(path: &Path, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    fs::write(path, encode_rgb(width, height, rgb))
+}
+
+pub fn save_rgba(path: &Path, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    fs::write(path, encode_rgba(width, height, rgba))
+}
+
+fn encode(width: usize, height: usize, pixels: &[u8], channels: usize, color_type: u8) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height * channels);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    // every row starts with its filter type, 0 for none
+    let stride = width * channels;
+    let mut scanlines = Vec::with_capacity((stride + 1) * height);
+    for row in pixels.chunks(stride.max(1)).take(height) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // the CRC covers the chunk type and data, not the length
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, no preset dictionary, fastest
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
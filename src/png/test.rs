@@ -0,0 +1,76 @@
+use crate::png::{adler32, crc32, encode_rgb, encode_rgba, zlib_stored};
+
+// chunk type and data of every chunk, checking lengths and CRCs
+fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    assert_eq!(
+        &png[..8],
+        &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+    );
+    let mut res = Vec::new();
+    let mut pos = 8;
+    while pos < png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let body = &png[pos + 4..pos + 8 + len];
+        let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+        assert_eq!(crc32(body), crc);
+        res.push((
+            String::from_utf8(body[..4].to_vec()).unwrap(),
+            body[4..].to_vec(),
+        ));
+        pos += len + 12;
+    }
+    res
+}
+
+// undoes zlib_stored
+fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 2;
+    loop {
+        let last = zlib[pos] & 1 == 1;
+        let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+        let nlen = u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]) as usize;
+        assert_eq!(len ^ 0xFFFF, nlen);
+        out.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+        if last {
+            break;
+        }
+    }
+    assert_eq!(&zlib[pos..], &adler32(&out).to_be_bytes());
+    out
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn test_zlib_stored_blocks() {
+    let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let zlib = zlib_stored(&data);
+    assert_eq!(&zlib[..2], &[0x78, 0x01]);
+    assert_eq!(inflate_stored(&zlib), data);
+    assert_eq!(inflate_stored(&zlib_stored(&[])), Vec::<u8>::new());
+}
+
+#[test]
+fn test_encode_rgba() {
+    let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
+    let chunks = chunks(&encode_rgba(2, 1, &rgba));
+    let names: Vec<&str> = chunks.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["IHDR", "IDAT", "IEND"]);
+    assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+    assert_eq!(inflate_stored(&chunks[1].1), [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn test_encode_rgb() {
+    let rgb = [1, 2, 3, 4, 5, 6];
+    let chunks = chunks(&encode_rgb(1, 2, &rgb));
+    assert_eq!(chunks[0].1[9], 2);
+    assert_eq!(inflate_stored(&chunks[1].1), [0, 1, 2, 3, 0, 4, 5, 6]);
+}
@@ -0,0 +1,223 @@
+use std::{io, path::Path};
+
+use crate::png;
+
+use super::palette::Palette;
+use super::render::{FLIP_HORIZONTAL, FLIP_VERTICAL};
+use super::NesPPU;
+
+// Views of the PPU memory for debugging graphics without a GUI. They only
+// read state, so they can be taken at any point of a frame.
+
+const SCROLL_OUTLINE: (u8, u8, u8) = (0xFF, 0x00, 0xFF);
+const GRID: (u8, u8, u8) = (0x40, 0x40, 0x40);
+
+const SWATCH_SIZE: usize = 16;
+// OAM view: 8x8 sprites, each in a cell with a 1 pixel border
+const OAM_COLUMNS: usize = 8;
+const OAM_CELL_WIDTH: usize = 8 + 1;
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // RGBA, row by row
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    // fully transparent
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, (r, g, b): (u8, u8, u8)) {
+        let i = (y * self.width + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&[r, g, b, 0xFF]);
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: (u8, u8, u8)) {
+        for row in y..y + height {
+            for col in x..x + width {
+                self.set_pixel(col, row, color);
+            }
+        }
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgba(self.width, self.height, &self.pixels)
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        png::save_rgba(path, self.width, self.height, &self.pixels)
+    }
+}
+
+impl NesPPU {
+    // 2 bit color of a pixel of a tile, row and col from the top left
+    fn tile_pixel(&self, table: u16, tile: u16, row: u16, col: u16) -> u8 {
+        self.pattern_pixel(table + tile * 16 + row, col)
+    }
+
+    // same for the tile row whose low plane is at `addr`
+    fn pattern_pixel(&self, addr: u16, col: u16) -> u8 {
+        let low = self.read_vram(addr) >> (7 - col) & 1;
+        let high = self.read_vram(addr + 8) >> (7 - col) & 1;
+        high << 1 | low
+    }
+
+    // RGB of a color of one of the 8 palettes, 0-3 background, 4-7 sprites
+    fn palette_rgb(&self, palette: u8, color: u8, colors: &Palette) -> (u8, u8, u8) {
+        let index = if color == 0 { 0 } else { palette * 4 + color };
+        colors.rgb((self.read_vram(0x3F00 + index as u16) & 0x3F) as u16)
+    }
+
+    // Pattern table 0 ($0000) or 1 ($1000) as 16x16 tiles, 128x128 pixels,
+    // colored with one of the 8 palettes.
+    pub fn pattern_table_image(&self, table: u8, palette: u8, colors: &Palette) -> Image {
+        let base = (table as u16 & 1) * 0x1000;
+        let mut image = Image::new(128, 128);
+        for tile in 0..256u16 {
+            let (tile_x, tile_y) = ((tile % 16) as usize * 8, (tile / 16) as usize * 8);
+            for row in 0..8 {
+                for col in 0..8 {
+                    let color = self.tile_pixel(base, tile, row, col);
+                    let rgb = self.palette_rgb(palette & 7, color, colors);
+                    image.set_pixel(tile_x + col as usize, tile_y + row as usize, rgb);
+                }
+            }
+        }
+        image
+    }
+
+    // The four nametables as laid out at $2000-$2FFF, 512x480, so the
+    // cartridge mirroring shows. The 256x240 window the next frame starts
+    // from (t and fine X) is outlined, wrapping around like the scroll.
+    pub fn nametables_image(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(512, 480);
+        let table = self.ctrl.background_pattern_addr();
+        for y in 0..480 {
+            for x in 0..512 {
+                let nametable = 0x2000 + (y / 240 * 2 + x / 256) as u16 * 0x400;
+                let (tx, ty) = ((x % 256) as u16, (y % 240) as u16);
+                let tile = self.read_vram(nametable + ty / 8 * 32 + tx / 8) as u16;
+                let attribute = self.read_vram(nametable + 0x3C0 + ty / 32 * 8 + tx / 32);
+                let shift = (ty & 0x10) >> 2 | (tx & 0x10) >> 3;
+                let palette = (attribute >> shift) & 0x03;
+                let color = self.tile_pixel(table, tile, ty % 8, tx % 8);
+                image.set_pixel(x, y, self.palette_rgb(palette, color, colors));
+            }
+        }
+
+        let (scroll_x, scroll_y) = self.scroll_position();
+        for i in 0..256 {
+            image.set_pixel((scroll_x + i) % 512, scroll_y, SCROLL_OUTLINE);
+            image.set_pixel((scroll_x + i) % 512, (scroll_y + 239) % 480, SCROLL_OUTLINE);
+        }
+        for i in 0..240 {
+            image.set_pixel(scroll_x, (scroll_y + i) % 480, SCROLL_OUTLINE);
+            image.set_pixel((scroll_x + 255) % 512, (scroll_y + i) % 480, SCROLL_OUTLINE);
+        }
+        image
+    }
+
+    // top left of the screen in the 512x480 nametable view
+    pub fn scroll_position(&self) -> (usize, usize) {
+        let t = self.t as usize;
+        let x = ((t & 0x1F) << 3 | self.x as usize) + (t >> 10 & 1) * 256;
+        let y = ((t >> 5) & 0x1F) * 8 + (t >> 12 & 7) + (t >> 11 & 1) * 240;
+        (x, y % 480)
+    }
+
+    // All 64 sprites in OAM order, 8 per row, drawn with their palette and
+    // flips. Transparent pixels stay transparent, cells are split by a grid.
+    pub fn oam_image(&self, colors: &Palette) -> Image {
+        let height = self.ctrl.sprite_height() as usize;
+        let cell_height = height + 1;
+        let mut image = Image::new(
+            OAM_COLUMNS * OAM_CELL_WIDTH + 1,
+            64 / OAM_COLUMNS * cell_height + 1,
+        );
+        for i in 0..=OAM_COLUMNS {
+            image.fill(i * OAM_CELL_WIDTH, 0, 1, image.height, GRID);
+        }
+        for i in 0..=64 / OAM_COLUMNS {
+            image.fill(0, i * cell_height, image.width, 1, GRID);
+        }
+
+        for index in 0..64 {
+            let tile = self.oam_data[index * 4 + 1];
+            let attributes = self.oam_data[index * 4 + 2];
+            let cell_x = (index % OAM_COLUMNS) * OAM_CELL_WIDTH + 1;
+            let cell_y = (index / OAM_COLUMNS) * cell_height + 1;
+            for row in 0..height {
+                let addr = self.sprite_tile_addr(tile, row, attributes & FLIP_VERTICAL != 0);
+                for col in 0..8 {
+                    let flipped_col = if attributes & FLIP_HORIZONTAL != 0 {
+                        7 - col
+                    } else {
+                        col
+                    };
+                    let color = self.pattern_pixel(addr, flipped_col as u16);
+                    if color != 0 {
+                        let rgb = self.palette_rgb(4 + (attributes & 0x03), color, colors);
+                        image.set_pixel(cell_x + col, cell_y + row, rgb);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    // one line per sprite: position, tile and attributes decoded
+    pub fn oam_listing(&self) -> Vec<String> {
+        self.oam_data
+            .chunks(4)
+            .enumerate()
+            .map(|(index, sprite)| {
+                let attributes = sprite[2];
+                format!(
+                    "{:02}: x={:3} y={:3} tile=${:02X} palette={} {}{}{}",
+                    index,
+                    sprite[3],
+                    sprite[0],
+                    sprite[1],
+                    attributes & 0x03,
+                    if attributes & 0x40 != 0 { "H" } else { "-" },
+                    if attributes & 0x80 != 0 { "V" } else { "-" },
+                    if attributes & 0x20 != 0 {
+                        " behind"
+                    } else {
+                        ""
+                    },
+                )
+            })
+            .collect()
+    }
+
+    // Palette RAM as 16x16 swatches: background palettes on the top row,
+    // sprite palettes below, 256x32.
+    pub fn palette_image(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(16 * SWATCH_SIZE, 2 * SWATCH_SIZE);
+        for index in 0..32u16 {
+            let rgb = colors.rgb((self.read_vram(0x3F00 + index) & 0x3F) as u16);
+            let (x, y) = ((index % 16) as usize, (index / 16) as usize);
+            image.fill(
+                x * SWATCH_SIZE,
+                y * SWATCH_SIZE,
+                SWATCH_SIZE,
+                SWATCH_SIZE,
+                rgb,
+            );
+        }
+        image
+    }
+}
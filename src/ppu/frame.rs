@@ -0,0 +1,48 @@
+use super::palette::Palette;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+// The picture the PPU outputs, row by row. Owned by the core so frontends
+// and tests read the same thing. Each pixel is what the PPU puts on the
+// video line rather than a color: bits 0-5 the palette entry, bits 6-8 the
+// PPUMASK emphasis bits. A Palette (or the NTSC filter) turns it into RGB.
+pub struct Frame {
+    pub pixels: Vec<u16>,
+    // NTSC color subcarrier phase (0-11) at the first pixel, it moves from
+    // frame to frame which is what makes the dot crawl
+    pub phase: u8,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame {
+            pixels: vec![0; WIDTH * HEIGHT],
+            phase: 0,
+        }
+    }
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame::default()
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: u16) {
+        self.pixels[y * WIDTH + x] = pixel;
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * WIDTH + x]
+    }
+
+    // RGB24, row by row
+    pub fn to_rgb(&self, palette: &Palette) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for pixel in self.pixels.iter() {
+            let (r, g, b) = palette.rgb(*pixel);
+            rgb.extend_from_slice(&[r, g, b]);
+        }
+        rgb
+    }
+}
@@ -0,0 +1,441 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::bus::Device;
+use crate::cartridge::{Mirroring, CHR_ROM_PAGE_SIZE};
+use crate::cdl::CodeDataLogger;
+use crate::region::Region;
+
+use self::frame::Frame;
+use self::registers::{ControlRegister, MaskRegister, StatusRegister};
+use self::render::{LineSprite, Pipeline};
+
+pub mod debug;
+pub mod frame;
+pub mod palette;
+pub mod registers;
+mod render;
+pub mod test;
+
+//  _______________ $4000
+// | Mirrors       |
+// | $3F00-$3F1F   |
+// |_ _ _ _ _ _ _ _| $3F20
+// | Palettes      |
+// |_______________| $3F00
+// | Mirrors       |
+// | $2000-$2EFF   |
+// |_ _ _ _ _ _ _ _| $3000
+// | Nametables    |
+// |_______________| $2000
+// | Pattern       |
+// | Tables        |
+// |_______________| $0000
+
+const NAMETABLES: u16 = 0x2000;
+const NAMETABLES_END: u16 = 0x3EFF;
+const PALETTES: u16 = 0x3F00;
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+
+pub struct NesPPU {
+    pub chr_rom: Vec<u8>,
+    // cartridges without CHR-ROM come with 8K of CHR-RAM instead
+    chr_is_ram: bool,
+    pub mirroring: Mirroring,
+    // 2K on the console, four-screen carts add the other 2K
+    pub vram: [u8; 4096],
+    pub palette_table: [u8; 32],
+    pub oam_data: [u8; 256],
+    pub oam_addr: u8,
+
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+
+    // Internal scroll/address registers as described on the nesdev wiki:
+    // v current VRAM address, t temporary address (top left of the screen),
+    // x fine X scroll, w first/second write toggle of $2005/$2006.
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+
+    // PPUDATA reads below the palettes lag one read behind
+    read_buffer: u8,
+    // last value written to any register, what write-only registers read as
+    io_latch: u8,
+
+    // scanline layout and master clock ticks per dot, see set_region
+    region: Region,
+    master_divider: u64,
+    // dots run since power on
+    dots: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame_count: u64,
+    odd_frame: bool,
+    pub frame: Frame,
+    frame_ready: bool,
+    // $2002 read one dot before vblank starts: the flag never gets set
+    suppress_vblank: bool,
+    // NMI edge waiting for the CPU to notice it
+    nmi_pending: bool,
+    pipeline: Pipeline,
+    line_sprites: Vec<LineSprite>,
+    // false draws every sprite of a line, no more flicker, but games that
+    // hide things behind the limit will show them
+    pub sprite_limit: bool,
+    // shared with the bus, which logs the PRG side
+    pub cdl: Option<Rc<RefCell<CodeDataLogger>>>,
+}
+
+impl NesPPU {
+    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        NesPPU {
+            chr_rom: if chr_is_ram {
+                vec![0; CHR_ROM_PAGE_SIZE]
+            } else {
+                chr_rom
+            },
+            chr_is_ram,
+            mirroring,
+            vram: [0; 4096],
+            palette_table: [0; 32],
+            oam_data: [0; 256],
+            oam_addr: 0,
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            region: Region::Ntsc,
+            master_divider: Region::Ntsc.timing().ppu_divider,
+            dots: 0,
+            scanline: 0,
+            dot: 0,
+            frame_count: 0,
+            odd_frame: false,
+            frame: Frame::new(),
+            frame_ready: false,
+            suppress_vblank: false,
+            nmi_pending: false,
+            pipeline: Pipeline::default(),
+            line_sprites: Vec::new(),
+            sprite_limit: true,
+            cdl: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.master_divider = region.timing().ppu_divider;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub(super) fn pre_render_scanline(&self) -> u16 {
+        self.region.pre_render_scanline()
+    }
+
+    pub fn power_on(&mut self) {
+        self.ctrl = ControlRegister::empty();
+        self.mask = MaskRegister::empty();
+        self.status = StatusRegister::empty();
+        self.oam_addr = 0;
+        self.v = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.read_buffer = 0;
+        self.io_latch = 0;
+        self.dots = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.frame_count = 0;
+        self.odd_frame = false;
+        self.frame.phase = 0;
+        self.frame_ready = false;
+        self.suppress_vblank = false;
+        self.nmi_pending = false;
+    }
+
+    // PPUSTATUS, OAMADDR and v are left alone by the reset line
+    pub fn reset(&mut self) {
+        self.ctrl = ControlRegister::empty();
+        self.mask = MaskRegister::empty();
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.read_buffer = 0;
+    }
+
+    // The PPU drives /NMI low while both the vblank flag and PPUCTRL bit 7
+    // are set. The CPU reacts to the edge, so this is true once per edge.
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    // true once per frame, when the PPU enters vblank
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    // Runs one dot: 341 per scanline, 262 scanlines (312 on PAL/Dendy).
+    // Lines 0-239 are drawn a pixel per dot, 241 (291 on Dendy) starts
+    // vblank and the last line is the pre-render line that gets the scroll
+    // ready for the next frame.
+    pub fn step(&mut self) {
+        let rendering = self.mask.rendering_enabled();
+        let vblank_scanline = self.region.vblank_scanline();
+        let pre_render_scanline = self.pre_render_scanline();
+        if rendering && (self.scanline < 240 || self.scanline == pre_render_scanline) {
+            self.run_fetches();
+        }
+        if self.scanline < 240 && (1..=256).contains(&self.dot) {
+            self.render_pixel(self.dot as usize - 1, self.scanline as usize);
+        }
+        match (self.scanline, self.dot) {
+            (line, 1) if line == vblank_scanline => {
+                if !self.suppress_vblank {
+                    self.status.insert(StatusRegister::VBLANK_STARTED);
+                    if self.ctrl.contains(ControlRegister::GENERATE_NMI) {
+                        self.nmi_pending = true;
+                    }
+                }
+                self.suppress_vblank = false;
+                self.frame_ready = true;
+            }
+            (line, 1) if line == pre_render_scanline => {
+                self.status.remove(
+                    StatusRegister::VBLANK_STARTED
+                        | StatusRegister::SPRITE_ZERO_HIT
+                        | StatusRegister::SPRITE_OVERFLOW,
+                );
+            }
+            _ => {}
+        }
+
+        self.dots += 1;
+        self.dot += 1;
+        // NTSC odd frames are one dot shorter when rendering, the
+        // pre-render line skips its last dot
+        let skip = rendering
+            && self.odd_frame
+            && self.region.skips_odd_frame_dot()
+            && self.scanline == pre_render_scanline
+            && self.dot == DOTS_PER_SCANLINE - 1;
+        if self.dot == DOTS_PER_SCANLINE || skip {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.region.scanlines_per_frame() {
+                self.scanline = 0;
+                self.frame_count += 1;
+                self.odd_frame = !self.odd_frame;
+                // 8 subcarrier samples per dot, 12 per color cycle
+                self.frame.phase = (self.dots * 8 % 12) as u8;
+            }
+        }
+    }
+
+    // Folds $2000-$3EFF onto the VRAM array following the cartridge
+    // mirroring:
+    //   Horizontal: [ A ] [ a ]    Vertical: [ A ] [ B ]
+    //               [ B ] [ b ]              [ a ] [ b ]
+    pub fn mirror_vram_addr(&self, addr: u16) -> usize {
+        let index = (addr - NAMETABLES) & 0x0FFF;
+        let table = index / 0x400;
+        let offset = index & 0x3FF;
+        let table = match self.mirroring {
+            Mirroring::VERTICAL => table % 2,
+            Mirroring::HORIZONTAL => table / 2,
+            Mirroring::FOUR_SCREEN => table,
+        };
+        (table * 0x400 + offset) as usize
+    }
+
+    // $3F10/$3F14/$3F18/$3F1C are the backdrop entries of the background
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    // PPU address space without any register side effect
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0..=0x1FFF => self.chr_rom[addr as usize % self.chr_rom.len()],
+            NAMETABLES..=NAMETABLES_END => self.vram[self.mirror_vram_addr(addr)],
+            _ => {
+                let color = self.palette_table[Self::palette_index(addr)];
+                if self.mask.contains(MaskRegister::GREYSCALE) {
+                    color & 0x30
+                } else {
+                    color
+                }
+            }
+        }
+    }
+
+    pub fn write_vram(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0..=0x1FFF => {
+                if self.chr_is_ram {
+                    let len = self.chr_rom.len();
+                    self.chr_rom[addr as usize % len] = data;
+                }
+            }
+            NAMETABLES..=NAMETABLES_END => {
+                let index = self.mirror_vram_addr(addr);
+                self.vram[index] = data;
+            }
+            _ => self.palette_table[Self::palette_index(addr)] = data & 0x3F,
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        self.v = self.v.wrapping_add(self.ctrl.vram_addr_increment()) & 0x7FFF;
+    }
+
+    fn write_ctrl(&mut self, data: u8) {
+        let nmi_was_enabled = self.ctrl.contains(ControlRegister::GENERATE_NMI);
+        self.ctrl = ControlRegister::from_bits_truncate(data);
+        // turning NMI on in the middle of vblank fires it right away
+        if !nmi_was_enabled
+            && self.ctrl.contains(ControlRegister::GENERATE_NMI)
+            && self.status.contains(StatusRegister::VBLANK_STARTED)
+        {
+            self.nmi_pending = true;
+        }
+        // nametable select lands in bits 10-11 of t
+        self.t = (self.t & 0xF3FF) | ((data as u16 & 0x03) << 10);
+    }
+
+    fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001F) | (data as u16 >> 3);
+            self.x = data & 0x07;
+        } else {
+            self.t = (self.t & 0x8C1F) | ((data as u16 & 0x07) << 12) | ((data as u16 & 0xF8) << 2);
+        }
+        self.w = !self.w;
+    }
+
+    fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    fn read_status(&mut self) -> u8 {
+        // the flag would go up on the very next dot: it reads as clear and
+        // stays clear for this frame
+        let vblank_scanline = self.region.vblank_scanline();
+        if self.scanline == vblank_scanline && self.dot == 1 {
+            self.suppress_vblank = true;
+        }
+        // right after the flag went up: it reads as set, but the NMI that
+        // came with it is cancelled
+        if self.scanline == vblank_scanline && (2..=3).contains(&self.dot) {
+            self.nmi_pending = false;
+        }
+        let data = self.status.bits() | (self.io_latch & 0x1F);
+        self.status.remove(StatusRegister::VBLANK_STARTED);
+        self.w = false;
+        data
+    }
+
+    fn log_chr_read(&self, addr: u16) {
+        if let Some(cdl) = &self.cdl {
+            cdl.borrow_mut()
+                .log_chr_read(addr as usize % self.chr_rom.len());
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let addr = self.v & 0x3FFF;
+        let data = if addr >= PALETTES {
+            // palettes answer right away, the buffer gets the nametable
+            // byte "under" them
+            self.read_buffer = self.read_vram(addr - 0x1000);
+            (self.read_vram(addr) & 0x3F) | (self.io_latch & 0xC0)
+        } else {
+            if addr < NAMETABLES {
+                self.log_chr_read(addr);
+            }
+            let data = self.read_buffer;
+            self.read_buffer = self.read_vram(addr);
+            data
+        };
+        self.increment_vram_addr();
+        data
+    }
+
+    fn write_data(&mut self, data: u8) {
+        self.write_vram(self.v, data);
+        self.increment_vram_addr();
+    }
+}
+
+// Registers as seen from the CPU, `addr` is already folded into
+// $2000-$2007.
+impl Device for NesPPU {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = match addr & 0x07 {
+            2 => self.read_status(),
+            4 => self.oam_data[self.oam_addr as usize],
+            7 => self.read_data(),
+            _ => self.io_latch,
+        };
+        self.io_latch = data;
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.io_latch = data;
+        match addr & 0x07 {
+            0 => self.write_ctrl(data),
+            1 => self.mask = MaskRegister::from_bits_truncate(data),
+            2 => {}
+            3 => self.oam_addr = data,
+            4 => {
+                self.oam_data[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => self.write_scroll(data),
+            6 => self.write_addr(data),
+            _ => self.write_data(data),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr & 0x07 {
+            2 => self.status.bits() | (self.io_latch & 0x1F),
+            4 => self.oam_data[self.oam_addr as usize],
+            7 if self.v & 0x3FFF >= PALETTES => self.read_vram(self.v),
+            7 => self.read_buffer,
+            _ => self.io_latch,
+        }
+    }
+
+    fn catch_up(&mut self, master_clock: u64) {
+        let target = master_clock / self.master_divider;
+        while self.dots < target {
+            self.step();
+        }
+    }
+}
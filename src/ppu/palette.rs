@@ -0,0 +1,111 @@
+use std::{fs, path::Path};
+
+// RGB output of the 2C02 for each of the 64 palette entries
+#[rustfmt::skip]
+pub static SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+    (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96),
+    (0xA1, 0x00, 0x5E), (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00),
+    (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00), (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E),
+    (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05), (0x05, 0x05, 0x05),
+    (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+    (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00),
+    (0xC4, 0x62, 0x00), (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55),
+    (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21), (0x09, 0x09, 0x09), (0x09, 0x09, 0x09),
+    (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF), (0xD4, 0x80, 0xFF),
+    (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+    (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4),
+    (0x05, 0xFB, 0xFF), (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D),
+    (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF), (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB),
+    (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0), (0xFF, 0xEF, 0xA6),
+    (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+// Emphasis darkens the two channels that aren't emphasized, to about 82%
+// on a 2C02. Entries $xE/$xF are black and stay black.
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+pub const PALETTE_SIZE: usize = 64;
+pub const FULL_PALETTE_SIZE: usize = PALETTE_SIZE * 8;
+
+// RGB for every pixel value a Frame can hold: 64 colors for each of the 8
+// emphasis combinations, red emphasis being bit 6 of the pixel.
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from_colors(&SYSTEM_PALETTE)
+    }
+}
+
+impl Palette {
+    // the built-in NTSC palette
+    pub fn ntsc() -> Self {
+        Palette::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw =
+            fs::read(path).map_err(|e| format!("can't read palette {}: {}", path.display(), e))?;
+        Self::from_bytes(&raw)
+    }
+
+    // .pal files: 192 bytes for the 64 base colors, emphasis variants are
+    // then computed; 1536 bytes when the file spells out all 8 of them
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, String> {
+        if raw.len() != 192 && raw.len() != 1536 {
+            return Err(format!(
+                "palette must be 192 or 1536 bytes, got {} bytes",
+                raw.len()
+            ));
+        }
+        let colors: Vec<(u8, u8, u8)> = raw.chunks(3).map(|c| (c[0], c[1], c[2])).collect();
+        if colors.len() == PALETTE_SIZE {
+            Ok(Palette::from_colors(&colors))
+        } else {
+            Ok(Palette { colors })
+        }
+    }
+
+    fn from_colors(base: &[(u8, u8, u8)]) -> Self {
+        let mut colors = Vec::with_capacity(FULL_PALETTE_SIZE);
+        for emphasis in 0..8u16 {
+            for (index, color) in base.iter().enumerate() {
+                colors.push(emphasize(index, *color, emphasis));
+            }
+        }
+        Palette { colors }
+    }
+
+    pub fn rgb(&self, pixel: u16) -> (u8, u8, u8) {
+        self.colors[pixel as usize % FULL_PALETTE_SIZE]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|(r, g, b)| [*r, *g, *b])
+            .collect()
+    }
+}
+
+fn emphasize(index: usize, (r, g, b): (u8, u8, u8), emphasis: u16) -> (u8, u8, u8) {
+    if emphasis == 0 || index & 0x0E == 0x0E {
+        return (r, g, b);
+    }
+    let dim = |channel: u8, emphasized: bool| {
+        if emphasized {
+            channel
+        } else {
+            (channel as f32 * EMPHASIS_ATTENUATION) as u8
+        }
+    };
+    (
+        dim(r, emphasis & 1 != 0),
+        dim(g, emphasis & 2 != 0),
+        dim(b, emphasis & 4 != 0),
+    )
+}
@@ -0,0 +1,76 @@
+bitflags! {
+    // $2000 PPUCTRL: VPHB SINN
+    pub struct ControlRegister: u8 {
+        const NAMETABLE1              = 0b0000_0001;
+        const NAMETABLE2              = 0b0000_0010;
+        const VRAM_ADD_INCREMENT      = 0b0000_0100;
+        const SPRITE_PATTERN_ADDR     = 0b0000_1000;
+        const BACKGROUND_PATTERN_ADDR = 0b0001_0000;
+        const SPRITE_SIZE             = 0b0010_0000;
+        const MASTER_SLAVE_SELECT     = 0b0100_0000;
+        const GENERATE_NMI            = 0b1000_0000;
+    }
+}
+
+impl ControlRegister {
+    pub fn vram_addr_increment(&self) -> u16 {
+        if self.contains(ControlRegister::VRAM_ADD_INCREMENT) {
+            32
+        } else {
+            1
+        }
+    }
+
+    pub fn sprite_pattern_addr(&self) -> u16 {
+        if self.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub fn background_pattern_addr(&self) -> u16 {
+        if self.contains(ControlRegister::BACKGROUND_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub fn sprite_height(&self) -> u8 {
+        if self.contains(ControlRegister::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+}
+
+bitflags! {
+    // $2001 PPUMASK: BGRs bMmG
+    pub struct MaskRegister: u8 {
+        const GREYSCALE            = 0b0000_0001;
+        const LEFTMOST_BACKGROUND  = 0b0000_0010;
+        const LEFTMOST_SPRITES     = 0b0000_0100;
+        const SHOW_BACKGROUND      = 0b0000_1000;
+        const SHOW_SPRITES         = 0b0001_0000;
+        const EMPHASISE_RED        = 0b0010_0000;
+        const EMPHASISE_GREEN      = 0b0100_0000;
+        const EMPHASISE_BLUE       = 0b1000_0000;
+    }
+}
+
+impl MaskRegister {
+    pub fn rendering_enabled(&self) -> bool {
+        self.intersects(MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES)
+    }
+}
+
+bitflags! {
+    // $2002 PPUSTATUS: VSO- ----, the low bits are stale PPU bus content
+    pub struct StatusRegister: u8 {
+        const SPRITE_OVERFLOW = 0b0010_0000;
+        const SPRITE_ZERO_HIT = 0b0100_0000;
+        const VBLANK_STARTED  = 0b1000_0000;
+    }
+}
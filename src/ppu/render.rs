@@ -0,0 +1,338 @@
+use super::registers::{MaskRegister, StatusRegister};
+use super::NesPPU;
+
+// v layout: yyy NN YYYYY XXXXX
+//           fine Y, nametable, coarse Y, coarse X
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+const SPRITES_PER_LINE: usize = 8;
+
+// OAM attribute byte: VHP- --pp
+pub(super) const FLIP_VERTICAL: u8 = 0x80;
+pub(super) const FLIP_HORIZONTAL: u8 = 0x40;
+const BEHIND_BACKGROUND: u8 = 0x20;
+
+// Background fetch pipeline: every 8 dots the next tile's nametable byte,
+// attribute and pattern bytes are fetched into latches, then loaded into
+// the low half of the 16 bit shift registers. Pixels come out of bit 15,
+// offset by fine X.
+#[derive(Default)]
+pub(super) struct Pipeline {
+    next_tile: u8,
+    next_palette: u8,
+    next_low: u8,
+    next_high: u8,
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+}
+
+// a sprite picked for the line being drawn, pattern already fetched
+pub(super) struct LineSprite {
+    x: u8,
+    low: u8,
+    high: u8,
+    attributes: u8,
+    zero: bool,
+}
+
+impl NesPPU {
+    fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v = (self.v & !COARSE_X) ^ NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // end of a line: next pixel row, into the next tile row and then the
+    // nametable below once past row 29 (rows 30/31 wrap without switching)
+    fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !FINE_Y;
+        let mut y = (self.v & COARSE_Y) >> 5;
+        if y == 29 {
+            y = 0;
+            self.v ^= NAMETABLE_Y;
+        } else if y == 31 {
+            y = 0;
+        } else {
+            y += 1;
+        }
+        self.v = (self.v & !COARSE_Y) | (y << 5);
+    }
+
+    fn copy_horizontal_bits(&mut self) {
+        let bits = COARSE_X | NAMETABLE_X;
+        self.v = (self.v & !bits) | (self.t & bits);
+    }
+
+    fn copy_vertical_bits(&mut self) {
+        let bits = FINE_Y | NAMETABLE_Y | COARSE_Y;
+        self.v = (self.v & !bits) | (self.t & bits);
+    }
+
+    fn fetch_nametable(&mut self) {
+        self.pipeline.next_tile = self.read_vram(0x2000 | (self.v & 0x0FFF));
+    }
+
+    fn fetch_attribute(&mut self) {
+        let v = self.v;
+        let attribute =
+            self.read_vram(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+        // which 16x16 quadrant of the 32x32 attribute area the tile is in
+        let shift = ((v >> 4) & 0x04) | (v & 0x02);
+        self.pipeline.next_palette = (attribute >> shift) & 0x03;
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        let fine_y = (self.v & FINE_Y) >> 12;
+        self.ctrl.background_pattern_addr() + self.pipeline.next_tile as u16 * 16 + fine_y
+    }
+
+    fn fetch_pattern_low(&mut self) {
+        let addr = self.background_pattern_addr();
+        self.log_chr_rendered(addr);
+        self.pipeline.next_low = self.read_vram(addr);
+    }
+
+    fn fetch_pattern_high(&mut self) {
+        let addr = self.background_pattern_addr() + 8;
+        self.log_chr_rendered(addr);
+        self.pipeline.next_high = self.read_vram(addr);
+    }
+
+    fn log_chr_rendered(&self, addr: u16) {
+        if let Some(cdl) = &self.cdl {
+            cdl.borrow_mut()
+                .log_chr_rendered(addr as usize % self.chr_rom.len());
+        }
+    }
+
+    fn load_shifters(&mut self) {
+        let p = &mut self.pipeline;
+        p.pattern_low = (p.pattern_low & 0xFF00) | p.next_low as u16;
+        p.pattern_high = (p.pattern_high & 0xFF00) | p.next_high as u16;
+        let low = if p.next_palette & 1 != 0 { 0xFF } else { 0x00 };
+        let high = if p.next_palette & 2 != 0 { 0xFF } else { 0x00 };
+        p.attribute_low = (p.attribute_low & 0xFF00) | low;
+        p.attribute_high = (p.attribute_high & 0xFF00) | high;
+    }
+
+    fn shift_background(&mut self) {
+        if !self.mask.contains(MaskRegister::SHOW_BACKGROUND) {
+            return;
+        }
+        let p = &mut self.pipeline;
+        p.pattern_low <<= 1;
+        p.pattern_high <<= 1;
+        p.attribute_low <<= 1;
+        p.attribute_high <<= 1;
+    }
+
+    // What the rendering circuitry does at the current dot of a visible or
+    // the pre-render line, only called while rendering is enabled.
+    pub(super) fn run_fetches(&mut self) {
+        let dot = self.dot;
+        if (2..258).contains(&dot) || (321..338).contains(&dot) {
+            self.shift_background();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_shifters();
+                    self.fetch_nametable();
+                }
+                2 => self.fetch_attribute(),
+                4 => self.fetch_pattern_low(),
+                6 => self.fetch_pattern_high(),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+        let pre_render = self.scanline == self.pre_render_scanline();
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_shifters();
+                self.copy_horizontal_bits();
+                let next_line = if pre_render {
+                    0
+                } else {
+                    self.scanline as usize + 1
+                };
+                self.load_sprites(next_line);
+            }
+            280..=304 if pre_render => self.copy_vertical_bits(),
+            _ => {}
+        }
+    }
+
+    fn sprite_in_range(&self, sprite_y: u8, line: usize) -> bool {
+        let row = line as i32 - sprite_y as i32;
+        row >= 0 && row < self.ctrl.sprite_height() as i32
+    }
+
+    // OAM indexes of the sprites showing on line y, in priority order.
+    // The search happens on the line before, which is why sprites show one
+    // line below their OAM Y. Past the 8th sprite the hardware keeps
+    // looking for overflow but also steps through the sprite bytes, so it
+    // compares tile numbers and attributes as Y coordinates.
+    fn evaluate_sprites(&mut self, y: usize) -> Vec<usize> {
+        let mut found = Vec::new();
+        if y == 0 {
+            return found;
+        }
+        let line = y - 1;
+        let mut n = 0;
+        while n < 64 && found.len() < SPRITES_PER_LINE {
+            if self.sprite_in_range(self.oam_data[n * 4], line) {
+                found.push(n);
+            }
+            n += 1;
+        }
+
+        let mut m = 0;
+        while n < 64 {
+            let y = self.oam_data[n * 4 + m];
+            if self.sprite_in_range(y, line) {
+                self.status.insert(StatusRegister::SPRITE_OVERFLOW);
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+        }
+
+        if !self.sprite_limit {
+            found = (0..64)
+                .filter(|n| self.sprite_in_range(self.oam_data[n * 4], line))
+                .collect();
+        }
+        found
+    }
+
+    // Pattern table address of the low plane of row `row` of a sprite, as
+    // it shows on screen, the high plane is 8 bytes further. Shared with
+    // the OAM view.
+    pub(super) fn sprite_tile_addr(&self, tile: u8, row: usize, flip_v: bool) -> u16 {
+        let height = self.ctrl.sprite_height() as usize;
+        let row = if flip_v { height - 1 - row } else { row };
+        let tile = tile as u16;
+        // 8x16 sprites pick their table with bit 0 of the tile number
+        let (table, tile) = if height == 16 {
+            ((tile & 1) * 0x1000, (tile & 0xFE) + (row as u16 / 8))
+        } else {
+            (self.ctrl.sprite_pattern_addr(), tile)
+        };
+        table + tile * 16 + (row as u16 % 8)
+    }
+
+    // pattern bits of one row of a sprite, bit 7 leftmost
+    fn sprite_row(&self, index: usize, y: usize) -> (u8, u8) {
+        let sprite_y = self.oam_data[index * 4] as usize;
+        let tile = self.oam_data[index * 4 + 1];
+        let attributes = self.oam_data[index * 4 + 2];
+
+        let addr = self.sprite_tile_addr(tile, y - 1 - sprite_y, attributes & FLIP_VERTICAL != 0);
+        self.log_chr_rendered(addr);
+        self.log_chr_rendered(addr + 8);
+        let (mut low, mut high) = (self.read_vram(addr), self.read_vram(addr + 8));
+        if attributes & FLIP_HORIZONTAL != 0 {
+            low = low.reverse_bits();
+            high = high.reverse_bits();
+        }
+        (low, high)
+    }
+
+    // sprite evaluation and pattern fetches for line y, done during the
+    // horizontal blank of the line before
+    fn load_sprites(&mut self, y: usize) {
+        let sprites = self.evaluate_sprites(y);
+        self.line_sprites = sprites
+            .into_iter()
+            .map(|index| {
+                let (low, high) = self.sprite_row(index, y);
+                LineSprite {
+                    x: self.oam_data[index * 4 + 3],
+                    low,
+                    high,
+                    attributes: self.oam_data[index * 4 + 2],
+                    zero: index == 0,
+                }
+            })
+            .collect();
+    }
+
+    // palette RAM index of the background at x, 0 when transparent
+    fn background_pixel(&self, x: usize) -> u8 {
+        if !self.mask.contains(MaskRegister::SHOW_BACKGROUND)
+            || (x < 8 && !self.mask.contains(MaskRegister::LEFTMOST_BACKGROUND))
+        {
+            return 0;
+        }
+        let p = &self.pipeline;
+        let bit = 0x8000 >> self.x;
+        let color = ((p.pattern_high & bit != 0) as u8) << 1 | (p.pattern_low & bit != 0) as u8;
+        if color == 0 {
+            return 0;
+        }
+        let palette =
+            ((p.attribute_high & bit != 0) as u8) << 1 | (p.attribute_low & bit != 0) as u8;
+        palette * 4 + color
+    }
+
+    // Lower OAM indexes win. The first opaque sprite owns the pixel even
+    // when it's behind the background, and hides sprites below it.
+    fn sprite_pixel(&mut self, x: usize, background: u8) -> Option<(u8, bool)> {
+        if !self.mask.contains(MaskRegister::SHOW_SPRITES)
+            || (x < 8 && !self.mask.contains(MaskRegister::LEFTMOST_SPRITES))
+        {
+            return None;
+        }
+        let mut pixel = None;
+        for sprite in self.line_sprites.iter() {
+            let offset = x as i32 - sprite.x as i32;
+            if !(0..8).contains(&offset) {
+                continue;
+            }
+            let bit = 7 - offset;
+            let color = ((sprite.high >> bit) & 1) << 1 | ((sprite.low >> bit) & 1);
+            if color == 0 {
+                continue;
+            }
+            if sprite.zero && background != 0 && x != 255 {
+                self.status.insert(StatusRegister::SPRITE_ZERO_HIT);
+            }
+            if pixel.is_none() {
+                let index = 0x10 + (sprite.attributes & 0x03) * 4 + color;
+                pixel = Some((index, sprite.attributes & BEHIND_BACKGROUND != 0));
+            }
+        }
+        pixel
+    }
+
+    pub(super) fn render_pixel(&mut self, x: usize, y: usize) {
+        let mut index = 0;
+        if self.mask.rendering_enabled() {
+            let background = self.background_pixel(x);
+            index = match self.sprite_pixel(x, background) {
+                Some((sprite, behind)) if !behind || background == 0 => sprite,
+                _ => background,
+            };
+        }
+        // greyscale is already applied by the palette RAM read
+        let color = self.read_vram(0x3F00 + index as u16) & 0x3F;
+        let mut emphasis = (self.mask.bits() >> 5) as u16;
+        // pixels keep emphasis in NTSC order, red in bit 6
+        if self.region.swaps_red_green_emphasis() {
+            emphasis = (emphasis & 0b100) | (emphasis & 1) << 1 | (emphasis & 2) >> 1;
+        }
+        self.frame.set_pixel(x, y, color as u16 | emphasis << 6);
+    }
+}
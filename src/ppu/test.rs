@@ -0,0 +1,760 @@
+use std::path::Path;
+
+use crate::{
+    bus::{Bus, Device},
+    cartridge::{test::gen_test_rom_with_program, Mirroring},
+    mem::Mem,
+    ppu::{
+        debug::Image,
+        frame::Frame,
+        palette::{Palette, SYSTEM_PALETTE},
+        registers::StatusRegister,
+        NesPPU,
+    },
+    region::Region,
+};
+
+pub fn new_ppu(mirroring: Mirroring) -> NesPPU {
+    NesPPU::new(vec![0; 0x2000], mirroring)
+}
+
+fn set_addr(ppu: &mut NesPPU, addr: u16) {
+    ppu.write(0x2006, (addr >> 8) as u8);
+    ppu.write(0x2006, (addr & 0xFF) as u8);
+}
+
+#[test]
+fn test_vram_writes() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    set_addr(&mut ppu, 0x2305);
+    ppu.write(0x2007, 0x66);
+    assert_eq!(ppu.vram[0x0305], 0x66);
+    assert_eq!(ppu.v, 0x2306);
+}
+
+#[test]
+fn test_vram_reads_are_buffered() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    ppu.vram[0x0305] = 0x66;
+    ppu.vram[0x0306] = 0x77;
+    set_addr(&mut ppu, 0x2305);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+    assert_eq!(ppu.read(0x2007), 0x77);
+}
+
+#[test]
+fn test_vram_increment_32() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    ppu.vram[0x01FF] = 0x66;
+    ppu.vram[0x01FF + 32] = 0x77;
+    ppu.write(0x2000, 0b100);
+    set_addr(&mut ppu, 0x21FF);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+    assert_eq!(ppu.read(0x2007), 0x77);
+    assert_eq!(ppu.v, 0x21FF + 96);
+}
+
+// Horizontal: [0x2000 A] [0x2400 a]
+//             [0x2800 B] [0x2C00 b]
+#[test]
+fn test_horizontal_mirroring() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    set_addr(&mut ppu, 0x2405);
+    ppu.write(0x2007, 0x66);
+    set_addr(&mut ppu, 0x2805);
+    ppu.write(0x2007, 0x77);
+
+    set_addr(&mut ppu, 0x2005);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+    set_addr(&mut ppu, 0x2C05);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x77);
+}
+
+// Vertical: [0x2000 A] [0x2400 B]
+//           [0x2800 a] [0x2C00 b]
+#[test]
+fn test_vertical_mirroring() {
+    let mut ppu = new_ppu(Mirroring::VERTICAL);
+    set_addr(&mut ppu, 0x2005);
+    ppu.write(0x2007, 0x66);
+    set_addr(&mut ppu, 0x2C05);
+    ppu.write(0x2007, 0x77);
+
+    set_addr(&mut ppu, 0x2805);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+    set_addr(&mut ppu, 0x2405);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x77);
+    // $3000-$3EFF mirrors the nametables
+    set_addr(&mut ppu, 0x3005);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+}
+
+#[test]
+fn test_palette_mirrors() {
+    let mut ppu = new_ppu(Mirroring::VERTICAL);
+    set_addr(&mut ppu, 0x3F10);
+    ppu.write(0x2007, 0x21);
+    assert_eq!(ppu.palette_table[0], 0x21);
+    set_addr(&mut ppu, 0x3F25);
+    ppu.write(0x2007, 0x0F);
+    assert_eq!(ppu.palette_table[5], 0x0F);
+
+    // no buffering for palettes
+    set_addr(&mut ppu, 0x3F00);
+    assert_eq!(ppu.read(0x2007), 0x21);
+}
+
+#[test]
+fn test_palette_read_fills_buffer_from_nametable() {
+    let mut ppu = new_ppu(Mirroring::VERTICAL);
+    // $3F00 sits over $2F00, the second physical nametable
+    ppu.vram[0x0700] = 0x55;
+    set_addr(&mut ppu, 0x3F00);
+    ppu.read(0x2007);
+    set_addr(&mut ppu, 0x0000);
+    assert_eq!(ppu.read(0x2007), 0x55);
+}
+
+#[test]
+fn test_status_clears_vblank_and_toggle() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    ppu.vram[0x0305] = 0x66;
+    ppu.write(0x2006, 0x21);
+    ppu.write(0x2006, 0x23);
+    ppu.write(0x2006, 0x05);
+    ppu.read(0x2007);
+    assert_ne!(ppu.read(0x2007), 0x66);
+
+    ppu.status.insert(StatusRegister::VBLANK_STARTED);
+    assert_eq!(ppu.peek(0x2002) >> 7, 1);
+    assert_eq!(ppu.read(0x2002) >> 7, 1);
+    assert_eq!(ppu.read(0x2002) >> 7, 0);
+
+    // toggle reset: the next $2006 write is the high byte again
+    ppu.write(0x2006, 0x23);
+    ppu.write(0x2006, 0x05);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x66);
+}
+
+#[test]
+// t grouped as fine Y, nametable, coarse Y, coarse X
+#[allow(clippy::unusual_byte_groupings)]
+fn test_scroll_writes() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    ppu.write(0x2000, 0b11);
+    ppu.write(0x2005, 0x7D);
+    assert_eq!(ppu.t, 0b000_11_00000_01111);
+    assert_eq!(ppu.x, 0b101);
+    ppu.write(0x2005, 0x5E);
+    assert_eq!(ppu.t, 0b110_11_01011_01111);
+    assert!(!ppu.w);
+}
+
+#[test]
+fn test_oam_data() {
+    let mut ppu = new_ppu(Mirroring::HORIZONTAL);
+    ppu.write(0x2003, 0x10);
+    ppu.write(0x2004, 0x66);
+    ppu.write(0x2004, 0x77);
+    ppu.write(0x2003, 0x10);
+    assert_eq!(ppu.read(0x2004), 0x66);
+    ppu.write(0x2003, 0x11);
+    assert_eq!(ppu.read(0x2004), 0x77);
+}
+
+#[test]
+fn test_chr_ram() {
+    let mut ppu = NesPPU::new(vec![], Mirroring::HORIZONTAL);
+    set_addr(&mut ppu, 0x0010);
+    ppu.write(0x2007, 0x42);
+    assert_eq!(ppu.chr_rom[0x10], 0x42);
+
+    let mut ppu = NesPPU::new(vec![0; 0x2000], Mirroring::HORIZONTAL);
+    set_addr(&mut ppu, 0x0010);
+    ppu.write(0x2007, 0x42);
+    assert_eq!(ppu.chr_rom[0x10], 0);
+}
+
+#[test]
+fn test_registers_on_the_bus() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    // $3456 mirrors $2006
+    bus.mem_write(0x3456, 0x23);
+    bus.mem_write(0x2006, 0x05);
+    bus.mem_write(0x2007, 0x66);
+    assert_eq!(bus.ppu().vram[0x0305], 0x66);
+    assert_eq!(bus.ppu().mirroring, Mirroring::HORIZONTAL);
+
+    bus.ppu_mut().status.insert(StatusRegister::VBLANK_STARTED);
+    assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+    assert_eq!(bus.mem_read(0x200A) & 0x80, 0x80);
+    assert_eq!(bus.mem_read(0x2002) & 0x80, 0);
+}
+
+// Tile 1 is solid color 1, tile 2 solid color 3
+pub fn gen_test_chr() -> Vec<u8> {
+    let mut chr = vec![0; 0x2000];
+    for row in 0..8 {
+        chr[16 + row] = 0xFF;
+        chr[32 + row] = 0xFF;
+        chr[32 + 8 + row] = 0xFF;
+    }
+    chr
+}
+
+const FRAME_MASTER_TICKS: u64 = 341 * 262 * 4;
+
+fn run_frames(ppu: &mut NesPPU, frames: u64) {
+    let target = (ppu.frame_count + frames) * FRAME_MASTER_TICKS;
+    ppu.catch_up(target);
+}
+
+fn background_ppu() -> NesPPU {
+    let mut ppu = NesPPU::new(gen_test_chr(), Mirroring::HORIZONTAL);
+    ppu.palette_table[0] = 0x0F;
+    ppu.palette_table[1] = 0x30;
+    ppu.palette_table[3] = 0x16;
+    ppu.palette_table[5] = 0x2A;
+    ppu.vram[0] = 1;
+    ppu.vram[33] = 2;
+    ppu.write(0x2001, 0b0000_1010);
+    ppu
+}
+
+#[test]
+fn test_background_tiles() {
+    let mut ppu = background_ppu();
+    // the first frame after power on misses the tiles the pre-render line
+    // would have fetched for line 0
+    run_frames(&mut ppu, 2);
+
+    let white = 0x30;
+    let black = 0x0F;
+    assert_eq!(ppu.frame.pixel(0, 0), white);
+    assert_eq!(ppu.frame.pixel(7, 7), white);
+    assert_eq!(ppu.frame.pixel(8, 0), black);
+    assert_eq!(ppu.frame.pixel(0, 8), black);
+    assert_eq!(ppu.frame.pixel(8, 8), 0x16);
+}
+
+#[test]
+fn test_background_attributes() {
+    let mut ppu = background_ppu();
+    // top left 16x16 area uses palette 1
+    ppu.vram[0x3C0] = 0b01;
+    run_frames(&mut ppu, 2);
+    assert_eq!(ppu.frame.pixel(0, 0), 0x2A);
+}
+
+#[test]
+fn test_background_scroll() {
+    let mut ppu = background_ppu();
+    // scroll gets set during vblank, ready for the pre-render line
+    ppu.catch_up(242 * 341 * 4);
+    // 4 pixels right, 8 down: tile 2 lands at the top left
+    ppu.write(0x2005, 4);
+    ppu.write(0x2005, 8);
+    ppu.catch_up(2 * FRAME_MASTER_TICKS);
+    assert_eq!(ppu.frame.pixel(4, 0), 0x16);
+    assert_eq!(ppu.frame.pixel(3, 0), 0x0F);
+    assert_eq!(ppu.frame.pixel(4, 8), 0x0F);
+}
+
+#[test]
+fn test_rendering_disabled_shows_backdrop() {
+    let mut ppu = background_ppu();
+    ppu.write(0x2001, 0);
+    run_frames(&mut ppu, 1);
+    assert_eq!(ppu.frame.pixel(0, 0), 0x0F);
+}
+
+#[test]
+fn test_emphasis_bits_in_pixels() {
+    let mut ppu = background_ppu();
+    // red and blue emphasis
+    ppu.write(0x2001, 0b1010_1010);
+    run_frames(&mut ppu, 2);
+    assert_eq!(ppu.frame.pixel(0, 0), 0x30 | 0b101 << 6);
+    assert_eq!(ppu.frame.pixel(8, 0), 0x0F | 0b101 << 6);
+}
+
+#[test]
+fn test_greyscale() {
+    let mut ppu = background_ppu();
+    ppu.write(0x2001, 0b0000_1011);
+    run_frames(&mut ppu, 2);
+    assert_eq!(ppu.frame.pixel(8, 8), 0x10);
+    assert_eq!(ppu.frame.pixel(0, 8), 0x00);
+}
+
+#[test]
+fn test_palette_emphasis_variants() {
+    let palette = Palette::default();
+    assert_eq!(palette.rgb(0x30), SYSTEM_PALETTE[0x30]);
+    // red emphasis keeps red and dims green and blue
+    let (r, g, b) = palette.rgb(0x30 | 0b001 << 6);
+    assert_eq!(r, 0xFF);
+    assert!(g < 0xFF && b < 0xFF);
+    // blacks don't change
+    assert_eq!(palette.rgb(0x0F | 0b111 << 6), SYSTEM_PALETTE[0x0F]);
+}
+
+#[test]
+fn test_palette_from_pal_file() {
+    let raw: Vec<u8> = (0..192).map(|i| i as u8).collect();
+    let palette = Palette::from_bytes(&raw).unwrap();
+    assert_eq!(palette.rgb(1), (3, 4, 5));
+    assert_eq!(palette.to_bytes().len(), 1536);
+    assert_eq!(&palette.to_bytes()[..192], &raw[..]);
+
+    let raw: Vec<u8> = (0..1536).map(|i| (i / 3) as u8).collect();
+    let palette = Palette::from_bytes(&raw).unwrap();
+    assert_eq!(palette.rgb(0x41), (65, 65, 65));
+    assert_eq!(palette.to_bytes(), raw);
+
+    assert!(Palette::from_bytes(&[0; 100]).is_err());
+    assert!(Palette::load(Path::new("/nonexistent.pal")).is_err());
+}
+
+#[test]
+fn test_frame_to_rgb() {
+    let mut frame = Frame::new();
+    frame.set_pixel(1, 0, 0x16);
+    let rgb = frame.to_rgb(&Palette::default());
+    assert_eq!(rgb.len(), 256 * 240 * 3);
+    let (r, g, b) = SYSTEM_PALETTE[0x16];
+    assert_eq!(&rgb[3..6], &[r, g, b]);
+}
+
+#[test]
+fn test_vblank_and_frames_on_the_bus() {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    // just short of the vblank line, 3 dots per CPU cycle
+    for _ in 0..(241 * 341 / 3 / 7) {
+        bus.tick(7);
+    }
+    assert!(!bus.poll_frame());
+    for _ in 0..200 {
+        bus.tick(7);
+    }
+    assert!(bus.poll_frame());
+    assert!(!bus.poll_frame());
+    assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+}
+
+fn set_sprite(ppu: &mut NesPPU, index: usize, y: u8, tile: u8, attributes: u8, x: u8) {
+    ppu.oam_data[index * 4..index * 4 + 4].copy_from_slice(&[y, tile, attributes, x]);
+}
+
+// sprites only, hidden OAM entries parked below the screen
+fn sprite_ppu() -> NesPPU {
+    let mut chr = gen_test_chr();
+    // tile 3: only the top left pixel, color 1
+    chr[48] = 0x80;
+    let mut ppu = NesPPU::new(chr, Mirroring::HORIZONTAL);
+    ppu.oam_data = [0xFF; 256];
+    ppu.palette_table[0] = 0x0F;
+    ppu.palette_table[1] = 0x30;
+    ppu.palette_table[0x11] = 0x16;
+    ppu.palette_table[0x15] = 0x2A;
+    ppu.write(0x2001, 0b0001_0110);
+    ppu
+}
+
+#[test]
+fn test_sprite_position_and_palette() {
+    let mut ppu = sprite_ppu();
+    set_sprite(&mut ppu, 0, 10, 1, 0x01, 20);
+    run_frames(&mut ppu, 1);
+    // shows one line below its OAM Y
+    assert_eq!(ppu.frame.pixel(20, 10), 0x0F);
+    assert_eq!(ppu.frame.pixel(20, 11), 0x2A);
+    assert_eq!(ppu.frame.pixel(27, 18), 0x2A);
+    assert_eq!(ppu.frame.pixel(28, 18), 0x0F);
+    assert_eq!(ppu.frame.pixel(27, 19), 0x0F);
+}
+
+#[test]
+fn test_sprite_flipping() {
+    let mut ppu = sprite_ppu();
+    set_sprite(&mut ppu, 0, 10, 3, 0x00, 20);
+    set_sprite(&mut ppu, 1, 10, 3, 0xC0, 40);
+    run_frames(&mut ppu, 1);
+    assert_eq!(ppu.frame.pixel(20, 11), 0x16);
+    assert_eq!(ppu.frame.pixel(40, 11), 0x0F);
+    assert_eq!(ppu.frame.pixel(47, 18), 0x16);
+}
+
+#[test]
+fn test_sprite_8x16() {
+    let mut ppu = sprite_ppu();
+    ppu.write(0x2000, 0b0010_0000);
+    // tiles 2 (solid color 3) and 3 (one pixel)
+    ppu.palette_table[0x13] = 0x21;
+    set_sprite(&mut ppu, 0, 10, 2, 0x00, 20);
+    run_frames(&mut ppu, 1);
+    assert_eq!(ppu.frame.pixel(21, 18), 0x21);
+    assert_eq!(ppu.frame.pixel(20, 19), 0x16);
+    assert_eq!(ppu.frame.pixel(21, 19), 0x0F);
+}
+
+#[test]
+fn test_sprite_priority() {
+    let mut ppu = sprite_ppu();
+    ppu.vram[0] = 1;
+    ppu.write(0x2001, 0b0001_1110);
+    // behind the background: hidden where the background is opaque, and
+    // it still hides sprite 1 below it
+    set_sprite(&mut ppu, 0, 0, 1, 0x20, 4);
+    set_sprite(&mut ppu, 1, 0, 1, 0x01, 4);
+    run_frames(&mut ppu, 1);
+    assert_eq!(ppu.frame.pixel(7, 1), 0x30);
+    assert_eq!(ppu.frame.pixel(8, 1), 0x16);
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let mut ppu = sprite_ppu();
+    ppu.vram[0] = 1;
+    ppu.write(0x2001, 0b0001_1110);
+    set_sprite(&mut ppu, 0, 30, 1, 0x00, 100);
+    ppu.catch_up(241 * 341 * 4);
+    assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+    let mut ppu = sprite_ppu();
+    ppu.vram[0] = 1;
+    ppu.write(0x2001, 0b0001_1110);
+    set_sprite(&mut ppu, 0, 4, 1, 0x00, 4);
+    ppu.catch_up(4 * 341 * 4);
+    assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+    ppu.catch_up(6 * 341 * 4);
+    assert!(ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+    // cleared on the pre-render line
+    ppu.catch_up(FRAME_MASTER_TICKS);
+    assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+}
+
+#[test]
+fn test_sprite_overflow_and_limit() {
+    let mut ppu = sprite_ppu();
+    for i in 0..9 {
+        set_sprite(&mut ppu, i, 50, 1, 0x00, i as u8 * 8);
+    }
+    ppu.catch_up(60 * 341 * 4);
+    assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+    assert_eq!(ppu.frame.pixel(56, 51), 0x16);
+    assert_eq!(ppu.frame.pixel(64, 51), 0x0F);
+
+    let mut ppu = sprite_ppu();
+    ppu.sprite_limit = false;
+    for i in 0..9 {
+        set_sprite(&mut ppu, i, 50, 1, 0x00, i as u8 * 8);
+    }
+    ppu.catch_up(60 * 341 * 4);
+    assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+    assert_eq!(ppu.frame.pixel(64, 51), 0x16);
+}
+
+#[test]
+fn test_sprite_overflow_hardware_bug() {
+    let mut ppu = sprite_ppu();
+    for i in 0..8 {
+        set_sprite(&mut ppu, i, 50, 1, 0x00, 0);
+    }
+    // The 9th sprite is off the line, so the search moves on to sprite 9
+    // but reads its tile number as the Y coordinate.
+    set_sprite(&mut ppu, 9, 0xFF, 48, 0x00, 0);
+    ppu.catch_up(60 * 341 * 4);
+    assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+}
+
+fn run_to(ppu: &mut NesPPU, scanline: u16, dot: u16) {
+    while !(ppu.scanline == scanline && ppu.dot == dot) {
+        ppu.step();
+    }
+}
+
+#[test]
+fn test_mid_frame_scroll_split() {
+    let mut ppu = background_ppu();
+    for i in 0..0x3C0 {
+        ppu.vram[i] = if i % 2 == 0 { 1 } else { 2 };
+    }
+    run_frames(&mut ppu, 1);
+    // status bar on top, the playfield below scrolls 8 pixels. Line 100
+    // already took its horizontal scroll at dot 257, the new one is copied
+    // at the end of line 101 and shows from line 102.
+    run_to(&mut ppu, 100, 300);
+    ppu.write(0x2005, 8);
+    run_to(&mut ppu, 241, 0);
+    assert_eq!(ppu.frame.pixel(0, 101), 0x30);
+    assert_eq!(ppu.frame.pixel(0, 102), 0x16);
+    assert_eq!(ppu.frame.pixel(8, 102), 0x30);
+}
+
+#[test]
+fn test_mid_frame_pattern_table_switch() {
+    let mut chr = gen_test_chr();
+    // tile 1 of the second table is solid color 3
+    for row in 0..8 {
+        chr[0x1000 + 16 + row] = 0xFF;
+        chr[0x1000 + 24 + row] = 0xFF;
+    }
+    let mut ppu = NesPPU::new(chr, Mirroring::HORIZONTAL);
+    ppu.palette_table[1] = 0x30;
+    ppu.palette_table[3] = 0x16;
+    ppu.vram[..0x3C0].fill(1);
+    ppu.write(0x2001, 0b0000_1010);
+    run_frames(&mut ppu, 1);
+    run_to(&mut ppu, 120, 340);
+    ppu.write(0x2000, 0b0001_0000);
+    run_to(&mut ppu, 241, 0);
+    // the two tiles prefetched for the next line still come from table 0
+    assert_eq!(ppu.frame.pixel(100, 120), 0x30);
+    assert_eq!(ppu.frame.pixel(15, 121), 0x30);
+    assert_eq!(ppu.frame.pixel(16, 121), 0x16);
+}
+
+#[test]
+fn test_odd_frame_skipped_dot() {
+    let mut ppu = background_ppu();
+    let mut dots = 0;
+    for _ in 0..2 {
+        let frame = ppu.frame_count;
+        while ppu.frame_count == frame {
+            ppu.step();
+            dots += 1;
+        }
+    }
+    assert_eq!(dots, 341 * 262 * 2 - 1);
+
+    // no skip with rendering off
+    let mut ppu = background_ppu();
+    ppu.write(0x2001, 0);
+    ppu.catch_up(2 * FRAME_MASTER_TICKS);
+    assert_eq!((ppu.frame_count, ppu.scanline, ppu.dot), (2, 0, 0));
+}
+
+#[test]
+fn test_vblank_flag_timing() {
+    let mut ppu = background_ppu();
+    run_to(&mut ppu, 241, 1);
+    assert_eq!(ppu.read(0x2002) & 0x80, 0);
+    ppu.step();
+    assert_eq!(ppu.read(0x2002) & 0x80, 0);
+    assert!(ppu.take_frame_ready());
+
+    // next frame, one dot later: the flag is already up
+    run_to(&mut ppu, 240, 0);
+    run_to(&mut ppu, 241, 2);
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x80);
+    assert_eq!(ppu.read(0x2002) & 0x80, 0);
+
+    // cleared at dot 1 of the pre-render line
+    run_to(&mut ppu, 241, 5);
+    ppu.status.insert(StatusRegister::VBLANK_STARTED);
+    run_to(&mut ppu, 261, 1);
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0x80);
+    ppu.step();
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0);
+}
+
+#[test]
+fn test_nmi_at_vblank() {
+    let mut ppu = background_ppu();
+    ppu.write(0x2000, 0x80);
+    run_to(&mut ppu, 241, 1);
+    assert!(!ppu.take_nmi());
+    ppu.step();
+    assert!(ppu.take_nmi());
+    assert!(!ppu.take_nmi());
+}
+
+#[test]
+fn test_nmi_enabled_during_vblank() {
+    let mut ppu = background_ppu();
+    run_to(&mut ppu, 250, 0);
+    ppu.write(0x2000, 0x80);
+    assert!(ppu.take_nmi());
+    // already enabled, no new edge
+    ppu.write(0x2000, 0x80);
+    assert!(!ppu.take_nmi());
+    // toggling it off and on again fires once more
+    ppu.write(0x2000, 0x00);
+    ppu.write(0x2000, 0x80);
+    assert!(ppu.take_nmi());
+
+    // not in vblank anymore
+    run_to(&mut ppu, 10, 0);
+    ppu.write(0x2000, 0x00);
+    ppu.write(0x2000, 0x80);
+    assert!(!ppu.take_nmi());
+}
+
+#[test]
+fn test_status_read_suppresses_nmi() {
+    // one dot before the flag: no flag, no NMI
+    let mut ppu = background_ppu();
+    ppu.write(0x2000, 0x80);
+    run_to(&mut ppu, 241, 1);
+    ppu.read(0x2002);
+    ppu.step();
+    assert!(!ppu.take_nmi());
+
+    // right as it went up: flag reads set, NMI cancelled
+    let mut ppu = background_ppu();
+    ppu.write(0x2000, 0x80);
+    run_to(&mut ppu, 241, 2);
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x80);
+    assert!(!ppu.take_nmi());
+
+    // a few dots later the NMI stands
+    let mut ppu = background_ppu();
+    ppu.write(0x2000, 0x80);
+    run_to(&mut ppu, 241, 4);
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x80);
+    assert!(ppu.take_nmi());
+}
+
+#[test]
+fn test_pattern_table_view() {
+    let ppu = background_ppu();
+    let image = ppu.pattern_table_image(0, 0, &Palette::default());
+    assert_eq!((image.width, image.height), (128, 128));
+    let rgba = |(r, g, b): (u8, u8, u8)| [r, g, b, 0xFF];
+    // tile 0 blank, tile 1 color 1, tile 2 color 3
+    assert_eq!(image.pixel(0, 0), rgba(SYSTEM_PALETTE[0x0F]));
+    assert_eq!(image.pixel(8, 7), rgba(SYSTEM_PALETTE[0x30]));
+    assert_eq!(image.pixel(23, 0), rgba(SYSTEM_PALETTE[0x16]));
+    let image = ppu.pattern_table_image(1, 0, &Palette::default());
+    assert_eq!(image.pixel(8, 7), rgba(SYSTEM_PALETTE[0x0F]));
+}
+
+#[test]
+fn test_nametables_view() {
+    let mut ppu = background_ppu();
+    ppu.write(0x2005, 16);
+    ppu.write(0x2005, 8);
+    let image = ppu.nametables_image(&Palette::default());
+    assert_eq!((image.width, image.height), (512, 480));
+    let rgba = |(r, g, b): (u8, u8, u8)| [r, g, b, 0xFF];
+    assert_eq!(image.pixel(0, 0), rgba(SYSTEM_PALETTE[0x30]));
+    // horizontal mirroring: $2400 shows $2000
+    assert_eq!(image.pixel(256, 0), rgba(SYSTEM_PALETTE[0x30]));
+    assert_eq!(image.pixel(265, 12), rgba(SYSTEM_PALETTE[0x16]));
+    assert_eq!(image.pixel(0, 240), rgba(SYSTEM_PALETTE[0x0F]));
+
+    // scroll window outline, right edge wrapping to the other side
+    assert_eq!(ppu.scroll_position(), (16, 8));
+    let outline = [0xFF, 0x00, 0xFF, 0xFF];
+    assert_eq!(image.pixel(16, 8), outline);
+    assert_eq!(image.pixel(271, 8), outline);
+    assert_eq!(image.pixel(16, 247), outline);
+    assert_ne!(image.pixel(17, 9), outline);
+
+    ppu.write(0x2000, 0b11);
+    ppu.write(0x2005, 0);
+    ppu.write(0x2005, 0);
+    assert_eq!(ppu.scroll_position(), (256, 240));
+}
+
+#[test]
+fn test_oam_view() {
+    let mut ppu = sprite_ppu();
+    set_sprite(&mut ppu, 0, 10, 3, 0x00, 20);
+    set_sprite(&mut ppu, 9, 10, 3, 0xC0, 40);
+    let image = ppu.oam_image(&Palette::default());
+    assert_eq!((image.width, image.height), (73, 73));
+    let (r, g, b) = SYSTEM_PALETTE[0x16];
+    assert_eq!(image.pixel(1, 1), [r, g, b, 0xFF]);
+    assert_eq!(image.pixel(2, 1)[3], 0);
+    // second row, second column, flipped both ways
+    assert_eq!(image.pixel(9 + 8, 9 + 8), [r, g, b, 0xFF]);
+
+    let listing = ppu.oam_listing();
+    assert_eq!(listing.len(), 64);
+    assert_eq!(listing[9], "09: x= 40 y= 10 tile=$03 palette=0 HV");
+}
+
+#[test]
+fn test_palette_view() {
+    let ppu = sprite_ppu();
+    let image = ppu.palette_image(&Palette::default());
+    assert_eq!((image.width, image.height), (256, 32));
+    let (r, g, b) = SYSTEM_PALETTE[0x16];
+    assert_eq!(image.pixel(16, 16), [r, g, b, 0xFF]);
+    assert_eq!(image.pixel(31, 31), [r, g, b, 0xFF]);
+}
+
+#[test]
+fn test_debug_view_png() {
+    let image = Image::new(3, 2);
+    let png = image.to_png();
+    assert_eq!(&png[1..4], b"PNG");
+    assert_eq!(&png[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+}
+
+#[test]
+fn test_pal_frame() {
+    let mut ppu = background_ppu();
+    ppu.set_region(Region::Pal);
+    let mut dots = 0;
+    for _ in 0..2 {
+        let frame = ppu.frame_count;
+        while ppu.frame_count == frame {
+            ppu.step();
+            dots += 1;
+        }
+    }
+    // no skipped dot
+    assert_eq!(dots, 341 * 312 * 2);
+
+    run_to(&mut ppu, 241, 1);
+    ppu.step();
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0x80);
+    run_to(&mut ppu, 311, 1);
+    ppu.step();
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0);
+}
+
+#[test]
+fn test_dendy_late_vblank() {
+    let mut ppu = background_ppu();
+    ppu.set_region(Region::Dendy);
+    ppu.write(0x2000, 0x80);
+    run_to(&mut ppu, 241, 5);
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0);
+    assert!(!ppu.take_nmi());
+    run_to(&mut ppu, 291, 2);
+    assert_eq!(ppu.peek(0x2002) & 0x80, 0x80);
+    assert!(ppu.take_nmi());
+}
+
+#[test]
+fn test_pal_emphasis_swap() {
+    let mut ppu = background_ppu();
+    ppu.set_region(Region::Pal);
+    // bit 5 is green emphasis on PAL, stored as NTSC's green bit
+    ppu.write(0x2001, 0b0010_1010);
+    run_frames(&mut ppu, 2);
+    assert_eq!(ppu.frame.pixel(0, 0), 0x30 | 0b010 << 6);
+}
+
+#[test]
+fn test_frame_subcarrier_phase() {
+    let mut ppu = background_ppu();
+    ppu.write(0x2001, 0);
+    run_frames(&mut ppu, 1);
+    // 341 * 262 dots of 8 samples, 12 samples per color cycle
+    assert_eq!(ppu.frame.phase, 4);
+    run_frames(&mut ppu, 1);
+    assert_eq!(ppu.frame.phase, 8);
+}
@@ -0,0 +1,142 @@
+use crate::scheduler::{Timing, DENDY, NTSC, PAL};
+
+pub mod test;
+
+// Console variants that run the same games at different timings:
+//           CPU clock     scanlines  vblank lines  frame rate
+// NTSC      1.789773 MHz  262        20            60.10 Hz
+// PAL       1.662607 MHz  312        70            50.01 Hz
+// Dendy     1.773448 MHz  312        20 (late)     50.01 Hz
+// Dendy keeps NTSC's CPU/PPU ratio and vblank length, its extra 50 lines
+// come between the picture and vblank so NTSC games keep working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// CPU cycles of the quarter frame clocks of the 4-step frame counter, the
+// 5-step sequence has its last one at FIVE_STEP_LAST instead
+const NTSC_FRAME_COUNTER_STEPS: [u32; 4] = [7457, 14913, 22371, 29829];
+const PAL_FRAME_COUNTER_STEPS: [u32; 4] = [8313, 16627, 24939, 33253];
+const NTSC_FIVE_STEP_LAST: u32 = 37281;
+const PAL_FIVE_STEP_LAST: u32 = 41565;
+
+impl Region {
+    // NES 2.0 header byte 12, bits 0-1. Multi-region carts run as NTSC.
+    pub fn from_nes2_timing(bits: u8) -> Self {
+        match bits & 0x03 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("unknown region: {}", name)),
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        match self {
+            Region::Ntsc => NTSC,
+            Region::Pal => PAL,
+            Region::Dendy => DENDY,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // line on which the vblank flag and NMI go up
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame() - 1
+    }
+
+    pub fn vblank_scanlines(&self) -> u16 {
+        self.pre_render_scanline() - self.vblank_scanline()
+    }
+
+    // only the NTSC PPU drops a dot on odd frames
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    // the 2C07 and the Dendy PPU have the red and green emphasis bits of
+    // PPUMASK the other way around
+    pub fn swaps_red_green_emphasis(&self) -> bool {
+        *self != Region::Ntsc
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let timing = self.timing();
+        let dots = 341.0 * self.scanlines_per_frame() as f64;
+        // half the frames are a dot short
+        let dots = if self.skips_odd_frame_dot() {
+            dots - 0.5
+        } else {
+            dots
+        };
+        timing.master_hz as f64 / timing.ppu_divider as f64 / dots
+    }
+
+    // APU tables, in CPU cycles. Dendy has the NTSC APU.
+    pub fn noise_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Pal => &PAL_NOISE_PERIODS,
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+        }
+    }
+
+    pub fn dmc_rates(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Pal => &PAL_DMC_RATES,
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+        }
+    }
+
+    pub fn frame_counter_steps(&self) -> &'static [u32; 4] {
+        match self {
+            Region::Pal => &PAL_FRAME_COUNTER_STEPS,
+            Region::Ntsc | Region::Dendy => &NTSC_FRAME_COUNTER_STEPS,
+        }
+    }
+
+    pub fn five_step_last(&self) -> u32 {
+        match self {
+            Region::Pal => PAL_FIVE_STEP_LAST,
+            Region::Ntsc | Region::Dendy => NTSC_FIVE_STEP_LAST,
+        }
+    }
+}
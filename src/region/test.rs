@@ -0,0 +1,72 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    region::Region,
+    scheduler::{DENDY, NTSC, PAL},
+};
+
+#[test]
+fn test_region_timing() {
+    assert_eq!(Region::Ntsc.timing(), NTSC);
+    assert_eq!(Region::Pal.timing(), PAL);
+    assert_eq!(Region::Dendy.timing(), DENDY);
+    assert_eq!(Region::Ntsc.scanlines_per_frame(), 262);
+    assert_eq!(Region::Pal.scanlines_per_frame(), 312);
+    assert_eq!(Region::Ntsc.vblank_scanlines(), 20);
+    assert_eq!(Region::Pal.vblank_scanlines(), 70);
+    assert_eq!(Region::Dendy.vblank_scanlines(), 20);
+    assert!((Region::Ntsc.frame_rate() - 60.0988).abs() < 0.001);
+    assert!((Region::Pal.frame_rate() - 50.007).abs() < 0.001);
+    assert!((Region::Dendy.frame_rate() - 50.007).abs() < 0.001);
+}
+
+#[test]
+fn test_apu_tables() {
+    assert_eq!(Region::Ntsc.noise_periods()[15], 4068);
+    assert_eq!(Region::Pal.noise_periods()[15], 3778);
+    assert_eq!(Region::Dendy.noise_periods()[15], 4068);
+    assert_eq!(Region::Ntsc.dmc_rates()[0], 428);
+    assert_eq!(Region::Pal.dmc_rates()[0], 398);
+    assert_eq!(Region::Pal.frame_counter_steps()[3], 33253);
+    assert_eq!(Region::Ntsc.five_step_last(), 37281);
+}
+
+#[test]
+fn test_region_names() {
+    assert_eq!(Region::from_name("PAL"), Ok(Region::Pal));
+    assert_eq!(Region::from_name("dendy"), Ok(Region::Dendy));
+    assert_eq!(
+        Region::from_name("secam"),
+        Err("unknown region: secam".to_string())
+    );
+    assert_eq!(Region::from_nes2_timing(2), Region::Ntsc);
+}
+
+// CPU cycles taken by 6 frames, give or take the cycle the vblank
+// lands in
+fn cycles_per_6_frames(region: Region) -> u64 {
+    let mut bus = Bus::new(gen_test_rom_with_program(&[]));
+    bus.set_region(region);
+    bus.power_on();
+    while !bus.poll_frame() {
+        bus.tick(1);
+    }
+    let start = bus.cycles();
+    for _ in 0..6 {
+        bus.tick(1);
+        while !bus.poll_frame() {
+            bus.tick(1);
+        }
+    }
+    bus.cycles() - start
+}
+
+#[test]
+fn test_frame_length_on_the_bus() {
+    // rendering is off so no dot is skipped: 341 * 262 * 6 / 3
+    assert!(cycles_per_6_frames(Region::Ntsc).abs_diff(178_684) <= 1);
+    // 341 * 312 * 6 / 3.2
+    assert!(cycles_per_6_frames(Region::Pal).abs_diff(199_485) <= 1);
+    // 341 * 312 * 6 / 3
+    assert!(cycles_per_6_frames(Region::Dendy).abs_diff(212_784) <= 1);
+}
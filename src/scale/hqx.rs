@@ -0,0 +1,289 @@
+use super::{channels, rotate, yuv, Image};
+
+// Maxim Stepin's hq2x, hq3x and hq4x. Every pixel E is compared with its
+// 8 neighbours in YUV, the 8 yes/no answers make a pattern and the pattern
+// picks, for every output subpixel, one of the hqx interpolations of E and
+// its neighbours. Where two neighbours both differ from E the choice also
+// depends on whether they are alike, which hqx tests at run time.
+//
+// The reference case tables are symmetric under rotation, so only the top
+// left corner is kept here, with the subpixels closest to it, and the
+// other corners look it up with the window rotated.
+
+// the hqx thresholds on Y, U and V
+const THRESHOLDS: [f32; 3] = [48.0, 7.0, 6.0];
+
+fn differ(a: u32, b: u32) -> bool {
+    if a == b {
+        return false;
+    }
+    let (a, b) = (yuv(a), yuv(b));
+    (0..3).any(|i| (a[i] - b[i]).abs() > THRESHOLDS[i])
+}
+
+// 3x3 window, row by row
+//   A B C
+//   D E F
+//   G H I
+const A: usize = 0;
+const B: usize = 1;
+const C: usize = 2;
+const D: usize = 3;
+const E: usize = 4;
+const F: usize = 5;
+const G: usize = 6;
+const H: usize = 7;
+const I: usize = 8;
+
+// how much of each window pixel goes in a subpixel, out of 16
+type Weights = [u8; 9];
+
+fn weights(parts: &[(usize, u8)]) -> Weights {
+    let mut res = [0; 9];
+    for (pos, weight) in parts {
+        res[*pos] += weight;
+    }
+    res
+}
+
+// the hqx interpolations, Interp1 to Interp10
+fn i1(a: usize, b: usize) -> Weights {
+    weights(&[(a, 12), (b, 4)])
+}
+fn i2(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 8), (b, 4), (c, 4)])
+}
+fn i3(a: usize, b: usize) -> Weights {
+    weights(&[(a, 14), (b, 2)])
+}
+fn i4(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 2), (b, 7), (c, 7)])
+}
+fn i5(a: usize, b: usize) -> Weights {
+    weights(&[(a, 8), (b, 8)])
+}
+fn i6(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 10), (b, 4), (c, 2)])
+}
+fn i7(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 12), (b, 2), (c, 2)])
+}
+fn i8(a: usize, b: usize) -> Weights {
+    weights(&[(a, 10), (b, 6)])
+}
+fn i9(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 4), (b, 6), (c, 6)])
+}
+fn i10(a: usize, b: usize, c: usize) -> Weights {
+    weights(&[(a, 14), (b, 1), (c, 1)])
+}
+fn center() -> Weights {
+    weights(&[(E, 16)])
+}
+
+// run time tests, set when the two neighbours are alike
+const EDGE_BD: u16 = 1 << 8;
+const EDGE_BF: u16 = 1 << 9;
+const EDGE_DH: u16 = 1 << 10;
+
+// What the top left corner sees: which neighbours differ from E and which
+// of the edges around the corner are solid.
+struct Corner {
+    a: bool,
+    b: bool,
+    c: bool,
+    d: bool,
+    f: bool,
+    h: bool,
+    // B and D alike, an edge cutting the corner
+    joined: bool,
+    // B and F alike, an edge cutting the top right corner
+    top_edge: bool,
+    // the edge cutting the top right corner runs on into this one, a
+    // shallow line, or the bottom left one's does, a steep line
+    shallow: bool,
+    steep: bool,
+    // this corner's edge runs on into the top right or bottom left corner
+    long_right: bool,
+    long_down: bool,
+}
+
+impl Corner {
+    fn new(key: u16) -> Self {
+        let bit = |pos: usize| {
+            let index = if pos < E { pos } else { pos - 1 };
+            key & (1 << index) != 0
+        };
+        let (a, b, c, d) = (bit(A), bit(B), bit(C), bit(D));
+        let (f, g, h) = (bit(F), bit(G), bit(H));
+        let joined = b && d && key & EDGE_BD != 0;
+        let top_edge = b && f && key & EDGE_BF != 0;
+        let left_edge = d && h && key & EDGE_DH != 0;
+        Corner {
+            a,
+            b,
+            c,
+            d,
+            f,
+            h,
+            joined,
+            top_edge,
+            shallow: a && b && !d && f && !c && top_edge,
+            steep: a && d && !b && h && !g && left_edge,
+            long_right: joined && c && !f && !a,
+            long_down: joined && g && !h && !a,
+        }
+    }
+}
+
+fn hq2x_corner(k: &Corner) -> Vec<Weights> {
+    let pixel = match (k.b, k.d) {
+        (false, false) => i2(E, D, B),
+        (true, false) if !k.a => i2(E, A, D),
+        (true, false) if k.shallow => i6(E, B, D),
+        (true, false) => i1(E, D),
+        (false, true) if !k.a => i2(E, A, B),
+        (false, true) if k.steep => i6(E, D, B),
+        (false, true) => i1(E, B),
+        (true, true) if !k.joined && k.a => center(),
+        (true, true) if !k.joined => i1(E, A),
+        (true, true) if k.long_right || k.long_down => i9(E, D, B),
+        (true, true) if k.f && k.h && k.a => i10(E, D, B),
+        (true, true) if k.f && k.h => i7(E, D, B),
+        (true, true) => i2(E, D, B),
+    };
+    vec![pixel]
+}
+
+// the corner subpixel and the one right of it, the middle one is E
+fn hq3x_corner(k: &Corner) -> Vec<Weights> {
+    let corner = match (k.b, k.d) {
+        (false, false) => i2(E, D, B),
+        (true, false) if !k.a => i1(E, A),
+        (true, false) if k.shallow => i2(E, D, B),
+        (true, false) => i1(E, D),
+        (false, true) if !k.a => i1(E, A),
+        (false, true) if k.steep => i2(E, D, B),
+        (false, true) => i1(E, B),
+        (true, true) if !k.joined && k.a => center(),
+        (true, true) if !k.joined => i1(E, A),
+        (true, true) if k.long_right || k.long_down => i5(D, B),
+        (true, true) if k.f && k.h => i2(E, D, B),
+        (true, true) => i4(E, D, B),
+    };
+    let top = if !k.b {
+        i1(E, B)
+    } else if k.shallow || k.long_right {
+        i1(B, E)
+    } else if k.long_down {
+        i1(E, B)
+    } else if (k.joined && k.a) || (k.top_edge && k.c) {
+        i3(E, B)
+    } else {
+        center()
+    };
+    vec![corner, top]
+}
+
+// the 2x2 subpixels of the corner: corner, right of it, below it, inner
+fn hq4x_corner(k: &Corner) -> Vec<Weights> {
+    match (k.b, k.d) {
+        (false, false) => vec![i2(E, B, D), i6(E, B, D), i6(E, D, B), i7(E, D, B)],
+        (true, false) if !k.a => vec![i8(E, A), i1(E, A), i6(E, D, A), i3(E, A)],
+        (true, false) if k.shallow => vec![i1(E, B), i1(B, E), i8(E, D), i3(E, D)],
+        (true, false) => vec![i8(E, D), i3(E, D), i8(E, D), i3(E, D)],
+        (false, true) if !k.a => vec![i8(E, A), i6(E, B, A), i1(E, A), i3(E, A)],
+        (false, true) if k.steep => vec![i1(E, D), i8(E, B), i1(D, E), i3(E, B)],
+        (false, true) => vec![i8(E, B), i8(E, B), i3(E, B), i3(E, B)],
+        (true, true) if !k.joined && k.a => vec![center(); 4],
+        (true, true) if !k.joined => vec![i8(E, A), i1(E, A), i1(E, A), i3(E, A)],
+        (true, true) if k.long_down => vec![i5(B, D), i2(B, E, D), i8(D, H), i7(E, B, D)],
+        (true, true) if k.long_right => vec![i5(B, D), i8(B, F), i2(D, E, B), i7(E, B, D)],
+        (true, true) if k.a => vec![i5(B, D), i5(B, E), i5(D, E), center()],
+        (true, true) => vec![i2(E, B, D), i1(E, B), i1(E, D), center()],
+    }
+}
+
+// subpixels of the top left corner the tables fill, in their order
+fn corner_subpixels(n: usize) -> &'static [(usize, usize)] {
+    match n {
+        2 => &[(0, 0)],
+        3 => &[(0, 0), (1, 0)],
+        _ => &[(0, 0), (1, 0), (0, 1), (1, 1)],
+    }
+}
+
+lazy_static! {
+    // hq2x, hq3x and hq4x tables, indexed by the 8 bit pattern with the
+    // EDGE_* bits on top
+    static ref TABLES: Vec<Vec<Vec<Weights>>> = (2..=4)
+        .map(|n| {
+            (0..(1u16 << 11))
+                .map(|key| {
+                    let corner = Corner::new(key);
+                    match n {
+                        2 => hq2x_corner(&corner),
+                        3 => hq3x_corner(&corner),
+                        _ => hq4x_corner(&corner),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+}
+
+fn blend(window: &[u32; 9], weights: &Weights) -> u32 {
+    let mut sum = [0u32; 3];
+    for (pixel, weight) in window.iter().zip(weights) {
+        if *weight == 0 {
+            continue;
+        }
+        for (s, c) in sum.iter_mut().zip(channels(*pixel)) {
+            *s += c as u32 * *weight as u32;
+        }
+    }
+    let [r, g, b] = sum.map(|s| s / 16);
+    r << 16 | g << 8 | b
+}
+
+pub(super) fn hqx(image: &Image, n: usize) -> Image {
+    let table = &TABLES[n - 2];
+    let subpixels = corner_subpixels(n);
+    let mut out = Image::new(image.width * n, image.height * n);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let window = image.neighbours(x, y);
+            let e = window[E];
+            if n == 3 {
+                out.set(x * 3 + 1, y * 3 + 1, e);
+            }
+            for turns in 0..4 {
+                // the window as the corner sees it
+                let mut seen = [0; 9];
+                for (pos, pixel) in seen.iter_mut().enumerate() {
+                    *pixel = window[rotate(pos, turns)];
+                }
+                let mut key = 0u16;
+                for (index, pos) in [A, B, C, D, F, G, H, I].into_iter().enumerate() {
+                    if differ(e, seen[pos]) {
+                        key |= 1 << index;
+                    }
+                }
+                for (edge, (p, q)) in [(EDGE_BD, (B, D)), (EDGE_BF, (B, F)), (EDGE_DH, (D, H))] {
+                    if !differ(seen[p], seen[q]) {
+                        key |= edge;
+                    }
+                }
+
+                for ((sx, sy), weights) in subpixels.iter().zip(&table[key as usize]) {
+                    let (mut sx, mut sy) = (*sx, *sy);
+                    for _ in 0..turns {
+                        (sx, sy) = (n - 1 - sy, sx);
+                    }
+                    out.set(x * n + sx, y * n + sy, blend(&seen, weights));
+                }
+            }
+        }
+    }
+    out
+}
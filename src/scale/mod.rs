@@ -0,0 +1,515 @@
+mod hqx;
+pub mod test;
+
+// Video filter stage between the core framebuffer (or the NTSC filter
+// output) and the frontend texture: a pixel art scaler and optional
+// scanlines. Works on RGB24 images, row by row.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaler {
+    Nearest(usize),
+    // AdvMAME scale2x/scale3x
+    Scale2x,
+    Scale3x,
+    // hq2x to hq4x
+    Hq(usize),
+    // 2x to 6x
+    Xbrz(usize),
+}
+
+impl Scaler {
+    // "nearest3", "scale2x", "hq4x", "xbrz5"...
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let name = name.to_ascii_lowercase();
+        let factor = |prefix: &str, suffix: &str, range: std::ops::RangeInclusive<usize>| {
+            name.strip_prefix(prefix)
+                .map(|rest| rest.strip_suffix(suffix).unwrap_or(rest))
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| range.contains(n))
+        };
+        match name.as_str() {
+            "scale2x" => return Ok(Scaler::Scale2x),
+            "scale3x" => return Ok(Scaler::Scale3x),
+            _ => {}
+        }
+        if let Some(n) = factor("nearest", "x", 1..=16) {
+            Ok(Scaler::Nearest(n))
+        } else if let Some(n) = factor("hq", "x", 2..=4) {
+            Ok(Scaler::Hq(n))
+        } else if let Some(n) = factor("xbrz", "x", 2..=6) {
+            Ok(Scaler::Xbrz(n))
+        } else {
+            Err(format!("unknown scaler: {}", name))
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        match self {
+            Scaler::Nearest(n) | Scaler::Hq(n) | Scaler::Xbrz(n) => *n,
+            Scaler::Scale2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+}
+
+pub struct VideoFilter {
+    pub scaler: Scaler,
+    // darkens the last output row of every source row, 0.0 leaves it
+    // alone, 1.0 makes it black
+    pub scanlines: Option<f32>,
+}
+
+impl VideoFilter {
+    pub fn new(scaler: Scaler) -> Self {
+        VideoFilter {
+            scaler,
+            scanlines: None,
+        }
+    }
+
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let factor = self.scaler.factor();
+        (width * factor, height * factor)
+    }
+
+    pub fn apply(&self, rgb: &[u8], width: usize, height: usize) -> Vec<u8> {
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let image = Image::from_rgb(rgb, width, height);
+        let mut scaled = match self.scaler {
+            Scaler::Nearest(n) => nearest(&image, n),
+            Scaler::Scale2x => scale2x(&image),
+            Scaler::Scale3x => scale3x(&image),
+            Scaler::Hq(n) => hqx::hqx(&image, n),
+            Scaler::Xbrz(n) => xbrz(&image, n),
+        };
+        if let Some(darkness) = self.scanlines {
+            darken_scanlines(&mut scaled, self.scaler.factor(), darkness);
+        }
+        scaled.to_rgb()
+    }
+}
+
+// 0xRRGGBB pixels, so neighbours compare in one go
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    fn from_rgb(rgb: &[u8], width: usize, height: usize) -> Self {
+        let pixels = rgb
+            .chunks(3)
+            .take(width * height)
+            .map(|c| (c[0] as u32) << 16 | (c[1] as u32) << 8 | c[2] as u32)
+            .collect();
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn to_rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+            .collect()
+    }
+
+    // out of range coordinates repeat the border
+    fn get(&self, x: isize, y: isize) -> u32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, pixel: u32) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+
+    // 3x3 neighbourhood, row by row, center at 4
+    fn neighbours(&self, x: usize, y: usize) -> [u32; 9] {
+        let (x, y) = (x as isize, y as isize);
+        let mut res = [0; 9];
+        for (i, pixel) in res.iter_mut().enumerate() {
+            *pixel = self.get(x + i as isize % 3 - 1, y + i as isize / 3 - 1);
+        }
+        res
+    }
+}
+
+fn channels(pixel: u32) -> [f32; 3] {
+    [
+        (pixel >> 16 & 0xFF) as f32,
+        (pixel >> 8 & 0xFF) as f32,
+        (pixel & 0xFF) as f32,
+    ]
+}
+
+// weighted per channel average of the colors
+fn mix(colors: &[(u32, f32)]) -> u32 {
+    let total: f32 = colors.iter().map(|(_, w)| w).sum();
+    let mut sum = [0.0; 3];
+    for (color, weight) in colors {
+        for (s, c) in sum.iter_mut().zip(channels(*color)) {
+            *s += c * weight;
+        }
+    }
+    let [r, g, b] = sum.map(|s| (s / total).round() as u32);
+    r << 16 | g << 8 | b
+}
+
+fn yuv(pixel: u32) -> [f32; 3] {
+    let [r, g, b] = channels(pixel);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.169 * r - 0.331 * g + 0.5 * b + 128.0,
+        0.5 * r - 0.419 * g - 0.081 * b + 128.0,
+    ]
+}
+
+// 3x3 window position seen after `turns` quarter turns clockwise, so the
+// top left corner's code can run for the other three
+fn rotate(pos: usize, turns: usize) -> usize {
+    let (mut x, mut y) = (pos % 3, pos / 3);
+    for _ in 0..turns {
+        (x, y) = (2 - y, x);
+    }
+    y * 3 + x
+}
+
+fn nearest(image: &Image, n: usize) -> Image {
+    let mut out = Image::new(image.width * n, image.height * n);
+    for y in 0..out.height {
+        for x in 0..out.width {
+            out.set(x, y, image.pixels[y / n * image.width + x / n]);
+        }
+    }
+    out
+}
+
+// Each pixel E becomes 2x2, a corner takes the color of its two
+// neighbours when they agree and the other two don't:
+//   . B .      E0 E1
+//   D E F  ->  E2 E3
+//   . H .
+fn scale2x(image: &Image) -> Image {
+    let mut out = Image::new(image.width * 2, image.height * 2);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let [_, b, _, d, e, f, _, h, _] = image.neighbours(x, y);
+            let mut block = [e; 4];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = d;
+                }
+                if b == f {
+                    block[1] = f;
+                }
+                if d == h {
+                    block[2] = d;
+                }
+                if h == f {
+                    block[3] = f;
+                }
+            }
+            for (i, pixel) in block.iter().enumerate() {
+                out.set(x * 2 + i % 2, y * 2 + i / 2, *pixel);
+            }
+        }
+    }
+    out
+}
+
+//   A B C      E0 E1 E2
+//   D E F  ->  E3 E4 E5
+//   G H I      E6 E7 E8
+fn scale3x(image: &Image) -> Image {
+    let mut out = Image::new(image.width * 3, image.height * 3);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let [a, b, c, d, e, f, g, h, i] = image.neighbours(x, y);
+            let mut block = [e; 9];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = d;
+                }
+                if (d == b && e != c) || (b == f && e != a) {
+                    block[1] = b;
+                }
+                if b == f {
+                    block[2] = f;
+                }
+                if (d == b && e != g) || (d == h && e != a) {
+                    block[3] = d;
+                }
+                if (b == f && e != i) || (h == f && e != c) {
+                    block[5] = f;
+                }
+                if d == h {
+                    block[6] = d;
+                }
+                if (d == h && e != i) || (h == f && e != g) {
+                    block[7] = h;
+                }
+                if h == f {
+                    block[8] = f;
+                }
+            }
+            for (n, pixel) in block.iter().enumerate() {
+                out.set(x * 3 + n % 3, y * 3 + n / 3, *pixel);
+            }
+        }
+    }
+    out
+}
+
+// xBRZ color distance: YCbCr with luma weighted
+fn xbrz_distance(a: u32, b: u32) -> f32 {
+    let [r1, g1, b1] = channels(a);
+    let [r2, g2, b2] = channels(b);
+    let (r, g, b) = (r1 - r2, g1 - g2, b1 - b2);
+    let y = 0.2627 * r + 0.678 * g + 0.0593 * b;
+    let cb = 0.5 / (1.0 - 0.0593) * (b - y);
+    let cr = 0.5 / (1.0 - 0.2627) * (r - y);
+    (y * y + cb * cb + cr * cr).sqrt()
+}
+
+// colors closer than this count as the same
+const XBRZ_EQUAL: f32 = 30.0;
+// a gradient this much stronger than the other one dominates
+const XBRZ_DOMINANCE: f32 = 3.6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Blend {
+    None,
+    Normal,
+    Dominant,
+}
+
+// xBRZ corner detection for the 2x2 block F G / J K, looking at the 4x4
+// area around it:
+//   A B C D
+//   E F G H
+//   I J K L
+//   M N O P
+// Returns how the corner of each of F, G, J and K touching the block
+// should be blended.
+fn xbrz_corners(k: &[u32; 16]) -> [Blend; 4] {
+    let [_, b, c, _, e, f, g, h, i, j, kk, l, _, n, o, _] = *k;
+    let mut res = [Blend::None; 4];
+    let eq = |a: u32, b: u32| xbrz_distance(a, b) < XBRZ_EQUAL;
+    if (eq(f, g) && eq(j, kk)) || (eq(f, j) && eq(g, kk)) {
+        return res;
+    }
+    let d = xbrz_distance;
+    let jg = d(i, f) + d(f, c) + d(n, kk) + d(kk, h) + 4.0 * d(j, g);
+    let fk = d(e, j) + d(j, o) + d(b, g) + d(g, l) + 4.0 * d(f, kk);
+    if jg < fk {
+        let blend = if XBRZ_DOMINANCE * jg < fk {
+            Blend::Dominant
+        } else {
+            Blend::Normal
+        };
+        if !eq(f, g) && !eq(f, j) {
+            res[0] = blend;
+        }
+        if !eq(kk, j) && !eq(kk, g) {
+            res[3] = blend;
+        }
+    } else if fk < jg {
+        let blend = if XBRZ_DOMINANCE * fk < jg {
+            Blend::Dominant
+        } else {
+            Blend::Normal
+        };
+        if !eq(j, f) && !eq(j, kk) {
+            res[2] = blend;
+        }
+        if !eq(g, f) && !eq(g, kk) {
+            res[1] = blend;
+        }
+    }
+    res
+}
+
+// Share of each subpixel inside a region of the output block, the block
+// running from 0.0 to 1.0 both ways, sampled on a grid.
+fn coverage(n: usize, inside: impl Fn(f32, f32) -> bool) -> Vec<f32> {
+    const SAMPLES: usize = 16;
+    let mut res = Vec::with_capacity(n * n);
+    for sy in 0..n {
+        for sx in 0..n {
+            let mut hits = 0;
+            for j in 0..SAMPLES {
+                for i in 0..SAMPLES {
+                    let u = (sx as f32 + (i as f32 + 0.5) / SAMPLES as f32) / n as f32;
+                    let v = (sy as f32 + (j as f32 + 0.5) / SAMPLES as f32) / n as f32;
+                    hits += inside(u, v) as usize;
+                }
+            }
+            res.push(hits as f32 / (SAMPLES * SAMPLES) as f32);
+        }
+    }
+    res
+}
+
+// a line this much more shallow (or steep) than the other way is followed
+// into the next pixel
+const XBRZ_STEEP: f32 = 2.2;
+
+// How much of each subpixel the bottom right corner's blend covers, for
+// the five ways xBRZ blends a corner.
+struct XbrzAlpha {
+    // a rounded corner, when a line through it isn't wanted
+    corner: Vec<f32>,
+    // 45 degree line through the middle of the corner's edges
+    diagonal: Vec<f32>,
+    // line from the corner's bottom edge to the middle of the right one
+    // of the pixel on the left, and the same turned for steep lines
+    shallow: Vec<f32>,
+    steep: Vec<f32>,
+    both: Vec<f32>,
+}
+
+impl XbrzAlpha {
+    fn new(n: usize) -> Self {
+        let shallow = |u: f32, v: f32| v > 1.0 - u / 2.0;
+        let steep = |u: f32, v: f32| u > 1.0 - v / 2.0;
+        XbrzAlpha {
+            corner: coverage(n, |u, v| {
+                u > 0.5 && v > 0.5 && (u - 0.5).powi(2) + (v - 0.5).powi(2) > 0.25
+            }),
+            diagonal: coverage(n, |u, v| u + v > 1.5),
+            shallow: coverage(n, shallow),
+            steep: coverage(n, steep),
+            both: coverage(n, |u, v| shallow(u, v) || steep(u, v)),
+        }
+    }
+}
+
+// xBRZ: every pixel gets the corner blending decided for the four 2x2
+// blocks it is part of. Each blended corner is then drawn seen from the
+// bottom right, with the window turned: the closer of the two neighbours on
+// the corner is blended in along a 45 degree line, or a shallower or
+// steeper one when the edge carries on into the next pixel, or only at the
+// very corner when a line would eat an isolated pixel.
+fn xbrz(image: &Image, n: usize) -> Image {
+    let (width, height) = (image.width as isize, image.height as isize);
+    // blend of each pixel's corners: top left, top right, bottom left,
+    // bottom right
+    let mut corners = vec![[Blend::None; 4]; image.pixels.len()];
+    for y in -1..height {
+        for x in -1..width {
+            let mut area = [0; 16];
+            for (i, pixel) in area.iter_mut().enumerate() {
+                *pixel = image.get(x + i as isize % 4 - 1, y + i as isize / 4 - 1);
+            }
+            let blend = xbrz_corners(&area);
+            // F, G, J, K and the corner of theirs the block touches
+            for (i, (dx, dy, corner)) in [(0, 0, 3), (1, 0, 2), (0, 1, 1), (1, 1, 0)]
+                .into_iter()
+                .enumerate()
+            {
+                let (px, py) = (x + dx, y + dy);
+                if blend[i] != Blend::None && (0..width).contains(&px) && (0..height).contains(&py)
+                {
+                    corners[(py * width + px) as usize][corner] = blend[i];
+                }
+            }
+        }
+    }
+
+    let alpha = XbrzAlpha::new(n);
+    let eq = |a: u32, b: u32| xbrz_distance(a, b) < XBRZ_EQUAL;
+    // corner of the pixel at 3x3 window position `pos`
+    let corner_at = |pos: usize| pos / 6 * 2 + pos % 3 / 2;
+    let mut out = Image::new(image.width * n, image.height * n);
+    let mut block = vec![0; n * n];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let window = image.neighbours(x, y);
+            let e = window[4];
+            let blends = corners[y * image.width + x];
+            block.fill(e);
+            for turns in 0..4 {
+                let blend = blends[corner_at(rotate(8, turns))];
+                if blend == Blend::None {
+                    continue;
+                }
+                let [_, b, c, d, _, f, g, h, i] =
+                    [0, 1, 2, 3, 4, 5, 6, 7, 8].map(|pos| window[rotate(pos, turns)]);
+                let top_right = blends[corner_at(rotate(2, turns))];
+                let bottom_left = blends[corner_at(rotate(6, turns))];
+
+                let line = blend == Blend::Dominant
+                    || !((top_right != Blend::None && !eq(e, g))
+                        || (bottom_left != Blend::None && !eq(e, c))
+                        // an L shape, only round its corner
+                        || (!eq(e, i) && eq(g, h) && eq(h, i) && eq(i, f) && eq(f, c)));
+                let color = if xbrz_distance(e, f) <= xbrz_distance(e, h) {
+                    f
+                } else {
+                    h
+                };
+                let coverage = if line {
+                    let (fg, hc) = (xbrz_distance(f, g), xbrz_distance(h, c));
+                    let shallow = XBRZ_STEEP * fg <= hc && e != g && d != g;
+                    let steep = XBRZ_STEEP * hc <= fg && e != c && b != c;
+                    match (shallow, steep) {
+                        (true, true) => &alpha.both,
+                        (true, false) => &alpha.shallow,
+                        (false, true) => &alpha.steep,
+                        (false, false) => &alpha.diagonal,
+                    }
+                } else {
+                    &alpha.corner
+                };
+
+                for sy in 0..n {
+                    for sx in 0..n {
+                        let a = coverage[sy * n + sx];
+                        if a == 0.0 {
+                            continue;
+                        }
+                        let (mut ox, mut oy) = (sx, sy);
+                        for _ in 0..turns {
+                            (ox, oy) = (n - 1 - oy, ox);
+                        }
+                        let pixel = &mut block[oy * n + ox];
+                        *pixel = mix(&[(*pixel, 1.0 - a), (color, a)]);
+                    }
+                }
+            }
+            for (i, pixel) in block.iter().enumerate() {
+                out.set(x * n + i % n, y * n + i / n, *pixel);
+            }
+        }
+    }
+    out
+}
+
+fn darken_scanlines(image: &mut Image, factor: usize, darkness: f32) {
+    // a single output row per source row has nothing to leave bright
+    if factor < 2 {
+        return;
+    }
+    let keep = 1.0 - darkness.clamp(0.0, 1.0);
+    for y in (factor - 1..image.height).step_by(factor) {
+        for x in 0..image.width {
+            let [r, g, b] = channels(image.get(x as isize, y as isize)).map(|c| (c * keep) as u32);
+            image.set(x, y, r << 16 | g << 8 | b);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::scale::{Scaler, VideoFilter};
+
+const BLACK: [u8; 3] = [0, 0, 0];
+const WHITE: [u8; 3] = [255, 255, 255];
+
+// RGB24 image from rows of '#' (white) and '.' (black)
+fn image(rows: &[&str]) -> (Vec<u8>, usize, usize) {
+    let rgb = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .flat_map(|c| if c == '#' { WHITE } else { BLACK })
+        .collect();
+    (rgb, rows[0].len(), rows.len())
+}
+
+fn pixel(rgb: &[u8], width: usize, x: usize, y: usize) -> [u8; 3] {
+    let i = (y * width + x) * 3;
+    [rgb[i], rgb[i + 1], rgb[i + 2]]
+}
+
+// a diagonal staircase, what the smoothing scalers round off
+fn diagonal() -> (Vec<u8>, usize, usize) {
+    image(&["#...", "##..", "###.", "####"])
+}
+
+#[test]
+fn test_scaler_names() {
+    assert_eq!(Scaler::from_name("nearest3"), Ok(Scaler::Nearest(3)));
+    assert_eq!(Scaler::from_name("Scale2x"), Ok(Scaler::Scale2x));
+    assert_eq!(Scaler::from_name("hq4x"), Ok(Scaler::Hq(4)));
+    assert_eq!(Scaler::from_name("xbrz6"), Ok(Scaler::Xbrz(6)));
+    assert!(Scaler::from_name("hq5x").is_err());
+    assert!(Scaler::from_name("smooth4x").is_err());
+    assert!(Scaler::from_name("bilinear").is_err());
+}
+
+#[test]
+fn test_output_sizes() {
+    let (rgb, width, height) = diagonal();
+    for scaler in [
+        Scaler::Nearest(1),
+        Scaler::Nearest(10),
+        Scaler::Scale2x,
+        Scaler::Scale3x,
+        Scaler::Hq(2),
+        Scaler::Hq(3),
+        Scaler::Hq(4),
+        Scaler::Xbrz(2),
+        Scaler::Xbrz(6),
+    ] {
+        let filter = VideoFilter::new(scaler);
+        let n = scaler.factor();
+        assert_eq!(filter.output_size(width, height), (width * n, height * n));
+        assert_eq!(
+            filter.apply(&rgb, width, height).len(),
+            width * n * height * n * 3
+        );
+    }
+}
+
+#[test]
+fn test_nearest() {
+    let (rgb, width, height) = diagonal();
+    let out = VideoFilter::new(Scaler::Nearest(3)).apply(&rgb, width, height);
+    assert_eq!(pixel(&out, 12, 2, 2), WHITE);
+    assert_eq!(pixel(&out, 12, 3, 2), BLACK);
+    assert_eq!(pixel(&out, 12, 3, 3), WHITE);
+}
+
+#[test]
+fn test_scale2x() {
+    let (rgb, width, height) = diagonal();
+    let out = VideoFilter::new(Scaler::Scale2x).apply(&rgb, width, height);
+    // the black corner of the step at (1, 0) gets filled in white
+    assert_eq!(pixel(&out, 8, 2, 1), WHITE);
+    assert_eq!(pixel(&out, 8, 3, 0), BLACK);
+    // flat areas stay as they are
+    assert_eq!(pixel(&out, 8, 0, 7), WHITE);
+    assert_eq!(pixel(&out, 8, 7, 0), BLACK);
+}
+
+#[test]
+fn test_scale3x() {
+    let (rgb, width, height) = diagonal();
+    let out = VideoFilter::new(Scaler::Scale3x).apply(&rgb, width, height);
+    assert_eq!(pixel(&out, 12, 3, 2), WHITE);
+    assert_eq!(pixel(&out, 12, 5, 0), BLACK);
+    assert_eq!(pixel(&out, 12, 11, 0), BLACK);
+}
+
+#[test]
+fn test_hqx_blends_edges() {
+    let (rgb, width, height) = diagonal();
+    let out = VideoFilter::new(Scaler::Hq(4)).apply(&rgb, width, height);
+    // a blended subpixel on the step, neither black nor white
+    let blended = (0..16)
+        .flat_map(|y| (0..16).map(move |x| (x, y)))
+        .any(|(x, y)| ![BLACK, WHITE].contains(&pixel(&out, 16, x, y)));
+    assert!(blended);
+    assert_eq!(pixel(&out, 16, 15, 0), BLACK);
+    assert_eq!(pixel(&out, 16, 0, 15), WHITE);
+
+    // hq2x keeps a lone pixel, its corners only take 1/16 of the two
+    // sides: (14 * 255 + 0 + 0) / 16
+    let (rgb, width, height) = image(&["...", ".#.", "..."]);
+    let out = VideoFilter::new(Scaler::Hq(2)).apply(&rgb, width, height);
+    for (x, y) in [(2, 2), (3, 2), (2, 3), (3, 3)] {
+        assert_eq!(pixel(&out, 6, x, y), [223, 223, 223]);
+    }
+
+    // a flat image stays flat
+    let (rgb, width, height) = image(&["##", "##"]);
+    let out = VideoFilter::new(Scaler::Hq(3)).apply(&rgb, width, height);
+    assert!(out.iter().all(|c| *c == 255));
+}
+
+#[test]
+fn test_xbrz_smooths_edges() {
+    let (rgb, width, height) = diagonal();
+    let out = VideoFilter::new(Scaler::Xbrz(4)).apply(&rgb, width, height);
+    // the outer corner of the white step at (1, 0) is cut, the inner
+    // corner of the black pixel at (2, 1) is filled
+    assert_ne!(pixel(&out, 16, 7, 0), WHITE);
+    assert_ne!(pixel(&out, 16, 8, 7), BLACK);
+    assert_eq!(pixel(&out, 16, 15, 0), BLACK);
+    assert_eq!(pixel(&out, 16, 0, 15), WHITE);
+
+    let (rgb, width, height) = image(&["#.#.", ".#.#", "#.#."]);
+    let out = VideoFilter::new(Scaler::Xbrz(2)).apply(&rgb, width, height);
+    // a checkerboard has no edge to follow
+    assert_eq!(
+        out,
+        VideoFilter::new(Scaler::Nearest(2)).apply(&rgb, width, height)
+    );
+}
+
+#[test]
+fn test_empty_input() {
+    for scaler in [
+        Scaler::Nearest(2),
+        Scaler::Scale3x,
+        Scaler::Hq(4),
+        Scaler::Xbrz(6),
+    ] {
+        let filter = VideoFilter::new(scaler);
+        assert!(filter.apply(&[], 0, 0).is_empty());
+        assert!(filter.apply(&[], 0, 240).is_empty());
+    }
+}
+
+#[test]
+fn test_scanlines() {
+    let (rgb, width, height) = image(&["##", "##"]);
+    let mut filter = VideoFilter::new(Scaler::Nearest(2));
+    filter.scanlines = Some(0.5);
+    let out = filter.apply(&rgb, width, height);
+    assert_eq!(pixel(&out, 4, 0, 0), WHITE);
+    assert_eq!(pixel(&out, 4, 0, 1), [127, 127, 127]);
+    assert_eq!(pixel(&out, 4, 3, 2), WHITE);
+    assert_eq!(pixel(&out, 4, 3, 3), [127, 127, 127]);
+
+    // nothing to darken at 1x
+    let mut filter = VideoFilter::new(Scaler::Nearest(1));
+    filter.scanlines = Some(1.0);
+    assert_eq!(filter.apply(&rgb, width, height), rgb);
+}
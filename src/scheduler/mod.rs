@@ -0,0 +1,116 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+pub mod test;
+
+// Every component runs off a divider of the console's master oscillator.
+// NTSC: 21.477272 MHz, CPU /12, PPU /4 -> 3 dots per CPU cycle.
+// PAL:  26.601712 MHz, CPU /16, PPU /5 -> 3.2 dots per CPU cycle.
+// Dendy: PAL crystal, CPU /15, PPU /5 -> 3 dots per CPU cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub master_hz: u64,
+    pub cpu_divider: u64,
+    pub ppu_divider: u64,
+}
+
+pub const NTSC: Timing = Timing {
+    master_hz: 21_477_272,
+    cpu_divider: 12,
+    ppu_divider: 4,
+};
+
+pub const PAL: Timing = Timing {
+    master_hz: 26_601_712,
+    cpu_divider: 16,
+    ppu_divider: 5,
+};
+
+pub const DENDY: Timing = Timing {
+    master_hz: 26_601_712,
+    cpu_divider: 15,
+    ppu_divider: 5,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    MapperIrq,
+    // page to copy into OAM
+    OamDma(u8),
+}
+
+// seq keeps events scheduled for the same tick in the order they were added
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Event {
+    at: u64,
+    seq: u64,
+    kind: EventKind,
+}
+
+// Owns time for the whole console. The CPU drives it forward, devices
+// catch up to master_clock() lazily when the CPU touches their registers,
+// and anything that has to happen at a given time goes in the event queue.
+pub struct Scheduler {
+    pub timing: Timing,
+    master: u64,
+    events: BinaryHeap<Reverse<Event>>,
+    seq: u64,
+}
+
+impl Scheduler {
+    pub fn new(timing: Timing) -> Self {
+        Scheduler {
+            timing,
+            master: 0,
+            events: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn master_clock(&self) -> u64 {
+        self.master
+    }
+
+    pub fn cpu_cycles(&self) -> u64 {
+        self.master / self.timing.cpu_divider
+    }
+
+    pub fn ppu_dots(&self) -> u64 {
+        self.master / self.timing.ppu_divider
+    }
+
+    pub fn advance_cpu(&mut self, cycles: u64) {
+        self.master += cycles * self.timing.cpu_divider;
+    }
+
+    pub fn schedule_at(&mut self, master: u64, kind: EventKind) {
+        self.seq += 1;
+        self.events.push(Reverse(Event {
+            at: master,
+            seq: self.seq,
+            kind,
+        }));
+    }
+
+    pub fn schedule_in(&mut self, cpu_cycles: u64, kind: EventKind) {
+        self.schedule_at(self.master + cpu_cycles * self.timing.cpu_divider, kind);
+    }
+
+    // next event whose time has come, earliest first
+    pub fn pop_due(&mut self) -> Option<EventKind> {
+        match self.events.peek() {
+            Some(Reverse(event)) if event.at <= self.master => {
+                self.events.pop().map(|Reverse(event)| event.kind)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.master = 0;
+        self.events.clear();
+    }
+}
@@ -0,0 +1,42 @@
+use crate::scheduler::{EventKind, Scheduler, NTSC, PAL};
+
+#[test]
+fn test_ppu_dots_per_cpu_cycle() {
+    let mut ntsc = Scheduler::new(NTSC);
+    ntsc.advance_cpu(10);
+    assert_eq!(ntsc.cpu_cycles(), 10);
+    assert_eq!(ntsc.ppu_dots(), 30);
+
+    let mut pal = Scheduler::new(PAL);
+    pal.advance_cpu(5);
+    assert_eq!(pal.cpu_cycles(), 5);
+    assert_eq!(pal.ppu_dots(), 16);
+}
+
+#[test]
+fn test_events_fire_in_time_order() {
+    let mut clock = Scheduler::new(NTSC);
+    clock.schedule_in(10, EventKind::MapperIrq);
+    clock.schedule_in(3, EventKind::OamDma(2));
+    clock.schedule_in(3, EventKind::OamDma(7));
+    assert_eq!(clock.pop_due(), None);
+
+    clock.advance_cpu(3);
+    assert_eq!(clock.pop_due(), Some(EventKind::OamDma(2)));
+    assert_eq!(clock.pop_due(), Some(EventKind::OamDma(7)));
+    assert_eq!(clock.pop_due(), None);
+
+    clock.advance_cpu(20);
+    assert_eq!(clock.pop_due(), Some(EventKind::MapperIrq));
+    assert_eq!(clock.pending(), 0);
+}
+
+#[test]
+fn test_reset() {
+    let mut clock = Scheduler::new(NTSC);
+    clock.advance_cpu(100);
+    clock.schedule_in(1, EventKind::MapperIrq);
+    clock.reset();
+    assert_eq!(clock.master_clock(), 0);
+    assert_eq!(clock.pending(), 0);
+}
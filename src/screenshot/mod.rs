@@ -0,0 +1,147 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ntsc::NtscFilter,
+    png,
+    ppu::{
+        frame::{Frame, HEIGHT, WIDTH},
+        palette::Palette,
+    },
+    scale::VideoFilter,
+};
+
+pub mod test;
+
+// Rows and columns hidden by the TV bezel, in PPU pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    // what most NTSC TVs cut: the 224 line picture games are made for
+    pub fn ntsc() -> Self {
+        Overscan {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ScreenshotOptions {
+    pub overscan: Overscan,
+    // colors from the composite simulation instead of the palette
+    pub ntsc: Option<NtscFilter>,
+    pub filter: Option<VideoFilter>,
+}
+
+// An RGB24 picture ready to be saved
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn from_rgb(rgb: Vec<u8>, width: usize, height: usize) -> Self {
+        Screenshot { width, height, rgb }
+    }
+
+    // The PPU frame as the frontend shows it: palette or NTSC colors,
+    // overscan cropped, then the video filter.
+    pub fn capture(frame: &Frame, palette: &Palette, options: &ScreenshotOptions) -> Self {
+        let shot = match &options.ntsc {
+            Some(ntsc) => Screenshot::from_rgb(ntsc.apply(frame), ntsc.width, HEIGHT),
+            None => Screenshot::from_rgb(frame.to_rgb(palette), WIDTH, HEIGHT),
+        };
+        // the NTSC output is wider than the frame, crop in proportion
+        let overscan = options.overscan;
+        let overscan = Overscan {
+            left: overscan.left * shot.width / WIDTH,
+            right: overscan.right * shot.width / WIDTH,
+            ..overscan
+        };
+        let shot = shot.crop(overscan);
+        match &options.filter {
+            Some(filter) => shot.filtered(filter),
+            None => shot,
+        }
+    }
+
+    // `overscan` in pixels of this picture
+    pub fn crop(self, overscan: Overscan) -> Self {
+        let width = self.width.saturating_sub(overscan.left + overscan.right);
+        let height = self.height.saturating_sub(overscan.top + overscan.bottom);
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in overscan.top..overscan.top + height {
+            let start = (y * self.width + overscan.left) * 3;
+            rgb.extend_from_slice(&self.rgb[start..start + width * 3]);
+        }
+        Screenshot { width, height, rgb }
+    }
+
+    pub fn filtered(self, filter: &VideoFilter) -> Self {
+        let (width, height) = filter.output_size(self.width, self.height);
+        let rgb = filter.apply(&self.rgb, self.width, self.height);
+        Screenshot { width, height, rgb }
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgb(self.width, self.height, &self.rgb)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        png::save_rgb(path, self.width, self.height, &self.rgb)
+    }
+
+    // saves as <dir>/<prefix>-YYYYMMDD-HHMMSS-mmm.png, UTC
+    pub fn save_timestamped(&self, dir: &Path, prefix: &str) -> io::Result<PathBuf> {
+        let path = dir.join(timestamped_name(prefix, SystemTime::now()));
+        self.save(&path)?;
+        Ok(path)
+    }
+}
+
+pub fn timestamped_name(prefix: &str, time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let seconds_of_day = secs % 86400;
+    format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.png",
+        prefix,
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// days since 1970-01-01 to a proleptic Gregorian date, Howard Hinnant's
+// algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    ntsc::NtscFilter,
+    ppu::{
+        frame::Frame,
+        palette::{Palette, SYSTEM_PALETTE},
+    },
+    scale::{Scaler, VideoFilter},
+    screenshot::{timestamped_name, Overscan, Screenshot, ScreenshotOptions},
+};
+
+// white frame with the top 8 and bottom 8 lines red
+fn test_frame() -> Frame {
+    let mut frame = Frame::new();
+    for y in 0..240 {
+        for x in 0..256 {
+            let pixel = if !(8..232).contains(&y) { 0x16 } else { 0x30 };
+            frame.set_pixel(x, y, pixel);
+        }
+    }
+    frame
+}
+
+fn rgb(color: (u8, u8, u8)) -> [u8; 3] {
+    [color.0, color.1, color.2]
+}
+
+#[test]
+fn test_capture() {
+    let shot = Screenshot::capture(
+        &test_frame(),
+        &Palette::default(),
+        &ScreenshotOptions::default(),
+    );
+    assert_eq!((shot.width, shot.height), (256, 240));
+    assert_eq!(&shot.rgb[..3], &rgb(SYSTEM_PALETTE[0x16]));
+}
+
+#[test]
+fn test_overscan_crop() {
+    let options = ScreenshotOptions {
+        overscan: Overscan::ntsc(),
+        ..Default::default()
+    };
+    let shot = Screenshot::capture(&test_frame(), &Palette::default(), &options);
+    assert_eq!((shot.width, shot.height), (256, 224));
+    assert!(shot.rgb.chunks(3).all(|c| c == rgb(SYSTEM_PALETTE[0x30])));
+
+    let shot = Screenshot::from_rgb((0..12).collect(), 2, 2).crop(Overscan {
+        left: 1,
+        top: 1,
+        ..Default::default()
+    });
+    assert_eq!((shot.width, shot.height), (1, 1));
+    assert_eq!(shot.rgb, [9, 10, 11]);
+}
+
+#[test]
+fn test_capture_through_filters() {
+    let options = ScreenshotOptions {
+        overscan: Overscan {
+            left: 8,
+            right: 8,
+            ..Overscan::ntsc()
+        },
+        ntsc: Some(NtscFilter::new(512)),
+        filter: Some(VideoFilter::new(Scaler::Nearest(2))),
+    };
+    let shot = Screenshot::capture(&test_frame(), &Palette::default(), &options);
+    // 512 wide NTSC output less 16 on each side, then doubled
+    assert_eq!((shot.width, shot.height), (960, 448));
+    assert_eq!(shot.rgb.len(), 960 * 448 * 3);
+}
+
+#[test]
+fn test_timestamped_name() {
+    let time = UNIX_EPOCH + Duration::from_millis(1_709_251_199_042);
+    assert_eq!(
+        timestamped_name("shot", time),
+        "shot-20240229-235959-042.png"
+    );
+    assert_eq!(
+        timestamped_name("shot", UNIX_EPOCH),
+        "shot-19700101-000000-000.png"
+    );
+}
+
+#[test]
+fn test_save_timestamped() {
+    let dir = std::env::temp_dir();
+    let shot = Screenshot::from_rgb(vec![0; 4 * 2 * 3], 4, 2);
+    let path = shot.save_timestamped(&dir, "rusty-nes-test").unwrap();
+    let png = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(path.starts_with(&dir));
+    assert_eq!(png, shot.to_png());
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::cartridge::PRG_ROM_PAGE_SIZE;
+
+pub mod test;
+
+// Labels living in PRG-ROM are keyed by their PRG-ROM offset so the same CPU
+// address can carry a different name depending on the bank mapped in.
+// Everything else (RAM, registers, save RAM) is keyed by CPU address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolAddr {
+    Cpu(u16),
+    PrgRom(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: SymbolAddr,
+    pub comment: Option<String>,
+}
+
+#[derive(Default)]
+pub struct SymbolTable {
+    by_addr: HashMap<SymbolAddr, Symbol>,
+    by_name: HashMap<String, SymbolAddr>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn add(&mut self, name: &str, addr: SymbolAddr, comment: Option<String>) {
+        if name.is_empty() {
+            return;
+        }
+        self.by_name.insert(name.to_string(), addr);
+        self.by_addr.insert(
+            addr,
+            Symbol {
+                name: name.to_string(),
+                addr,
+                comment,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.by_addr.values()
+    }
+
+    // prg_offset is where `addr` lands in PRG-ROM with the current banks, if
+    // it lands there at all
+    pub fn lookup(&self, addr: u16, prg_offset: Option<usize>) -> Option<&Symbol> {
+        prg_offset
+            .and_then(|offset| self.by_addr.get(&SymbolAddr::PrgRom(offset)))
+            .or_else(|| self.by_addr.get(&SymbolAddr::Cpu(addr)))
+    }
+
+    pub fn label(&self, addr: u16, prg_offset: Option<usize>) -> Option<&str> {
+        self.lookup(addr, prg_offset).map(|s| s.name.as_str())
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<SymbolAddr> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn format_addr(&self, addr: u16, prg_offset: Option<usize>) -> String {
+        match self.label(addr, prg_offset) {
+            Some(name) => format!("${:04X} <{}>", addr, name),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("can't read symbol file {}: {}", path.display(), e))?;
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if file_name.ends_with(".dbg") {
+            self.load_ca65_dbg(&content)
+        } else if file_name.ends_with(".mlb") {
+            self.load_mesen_mlb(&content)
+        } else if file_name.ends_with(".nl") {
+            self.load_fceux_nl(&content, nl_bank(&file_name)?)
+        } else {
+            Err(format!("unknown symbol file format: {}", file_name))
+        }
+    }
+
+    // FCEUX name list: "$C000#Reset#comment". `bank` is the 16K PRG bank
+    // the file describes (game.nes.2.nl), None for game.nes.ram.nl.
+    pub fn load_fceux_nl(&mut self, content: &str, bank: Option<usize>) -> Result<(), String> {
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(3, '#');
+            let addr = parts.next().unwrap_or("");
+            let name = parts.next().unwrap_or("");
+            let comment = parts
+                .next()
+                .map(|c| c.trim_end_matches('#').to_string())
+                .filter(|c| !c.is_empty());
+
+            // "$0300/10" describes an array, only its start gets the label
+            let addr = addr.trim_start_matches('$').split('/').next().unwrap_or("");
+            let addr = u16::from_str_radix(addr, 16)
+                .map_err(|_| format!("bad address in .nl at line {}", line_no + 1))?;
+
+            let sym_addr = match bank {
+                Some(bank) if addr >= 0x8000 => {
+                    SymbolAddr::PrgRom(bank * PRG_ROM_PAGE_SIZE + (addr as usize & 0x3FFF))
+                }
+                _ => SymbolAddr::Cpu(addr),
+            };
+            self.add(name, sym_addr, comment);
+        }
+        Ok(())
+    }
+
+    // Mesen label file: "P:1F00:label:comment" with P = PRG-ROM offset,
+    // R = internal RAM, S = save RAM, W = work RAM, G = register.
+    pub fn load_mesen_mlb(&mut self, content: &str) -> Result<(), String> {
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(4, ':');
+            let kind = parts.next().unwrap_or("");
+            let addr = parts.next().unwrap_or("");
+            let name = parts.next().unwrap_or("");
+            let comment = parts
+                .next()
+                .map(|c| c.to_string())
+                .filter(|c| !c.is_empty());
+
+            let addr = addr.split('-').next().unwrap_or("");
+            let bad_address = || format!("bad address in .mlb at line {}", line_no + 1);
+            let addr = usize::from_str_radix(addr, 16).map_err(|_| bad_address())?;
+
+            let sym_addr = match kind {
+                "P" => SymbolAddr::PrgRom(addr),
+                "R" | "G" if addr <= 0xFFFF => SymbolAddr::Cpu(addr as u16),
+                // save and work RAM are the 8K at $6000
+                "S" | "W" if addr < 0x2000 => SymbolAddr::Cpu(0x6000 + addr as u16),
+                "R" | "G" | "S" | "W" => return Err(bad_address()),
+                _ => continue,
+            };
+            self.add(name, sym_addr, comment);
+        }
+        Ok(())
+    }
+
+    // ca65/ld65 debug info: labels come from "sym" lines, segment lines tell
+    // where in the output file (and so in PRG-ROM) each label ends up.
+    pub fn load_ca65_dbg(&mut self, content: &str) -> Result<(), String> {
+        struct Segment {
+            start: usize,
+            file_offset: Option<usize>,
+        }
+        let mut segments: HashMap<String, Segment> = HashMap::new();
+        let mut syms = Vec::new();
+
+        for line in content.lines() {
+            let (kind, rest) = match line.split_once(|c: char| c.is_whitespace()) {
+                Some(split) => split,
+                None => continue,
+            };
+            let fields = parse_dbg_fields(rest);
+            match kind {
+                "seg" => {
+                    let id = fields.get("id").cloned().unwrap_or_default();
+                    let start = fields.get("start").and_then(|v| parse_dbg_num(v));
+                    let file_offset = fields.get("ooffs").and_then(|v| parse_dbg_num(v));
+                    if let Some(start) = start {
+                        segments.insert(id, Segment { start, file_offset });
+                    }
+                }
+                "sym" => syms.push(fields),
+                _ => {}
+            }
+        }
+
+        for fields in syms {
+            if fields.get("type").map(|t| t.as_str()) != Some("lab") {
+                continue;
+            }
+            let name = match fields.get("name") {
+                Some(name) => name,
+                None => continue,
+            };
+            let val = match fields.get("val").and_then(|v| parse_dbg_num(v)) {
+                Some(val) => val,
+                None => continue,
+            };
+            let segment = fields.get("seg").and_then(|seg| segments.get(seg));
+
+            // ooffs counts the 16 bytes iNES header
+            let sym_addr = match segment {
+                Some(Segment {
+                    start,
+                    file_offset: Some(file_offset),
+                }) if val >= 0x8000 && *file_offset >= 16 => {
+                    SymbolAddr::PrgRom(file_offset - 16 + (val - start))
+                }
+                _ => SymbolAddr::Cpu(val as u16),
+            };
+            self.add(name, sym_addr, None);
+        }
+        Ok(())
+    }
+}
+
+// FCEUX names its lists after the ROM: game.nes.ram.nl for RAM and
+// game.nes.1F.nl for a PRG bank, the bank number in uppercase hex
+fn nl_bank(file_name: &str) -> Result<Option<usize>, String> {
+    let bad_name = || {
+        format!(
+            "{} isn't an FCEUX name list, expected <rom>.nes.ram.nl or <rom>.nes.<bank>.nl",
+            file_name
+        )
+    };
+    let stem = file_name.strip_suffix(".nl").ok_or_else(bad_name)?;
+    let (rom, bank) = stem.rsplit_once('.').ok_or_else(bad_name)?;
+    if !rom.to_ascii_lowercase().ends_with(".nes") {
+        return Err(bad_name());
+    }
+    if bank == "ram" {
+        return Ok(None);
+    }
+    if bank.is_empty() || !bank.chars().all(|c| matches!(c, '0'..='9' | 'A'..='F')) {
+        return Err(bad_name());
+    }
+    usize::from_str_radix(bank, 16)
+        .map(Some)
+        .map_err(|_| bad_name())
+}
+
+fn parse_dbg_fields(rest: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut in_quotes = false;
+
+    for c in rest.trim().chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '=' if !in_quotes && !in_value => in_value = true,
+            ',' if !in_quotes => {
+                fields.insert(std::mem::take(&mut key), std::mem::take(&mut value));
+                in_value = false;
+            }
+            _ if in_value => value.push(c),
+            _ => key.push(c),
+        }
+    }
+    if !key.is_empty() {
+        fields.insert(key, value);
+    }
+    fields
+}
+
+fn parse_dbg_num(value: &str) -> Option<usize> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
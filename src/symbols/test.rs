@@ -0,0 +1,89 @@
+use crate::symbols::{nl_bank, SymbolAddr, SymbolTable};
+
+#[test]
+fn test_fceux_nl() {
+    let mut symbols = SymbolTable::new();
+    symbols
+        .load_fceux_nl("$0300/10#player_x#x position\n$00FF#last_key#\n", None)
+        .unwrap();
+    symbols
+        .load_fceux_nl("$C012#main_loop#\n$8000#reset#\n", Some(1))
+        .unwrap();
+
+    assert_eq!(symbols.label(0x0300, None), Some("player_x"));
+    assert_eq!(
+        symbols.lookup(0x0300, None).unwrap().comment,
+        Some("x position".to_string())
+    );
+    assert_eq!(symbols.label(0x00FF, None), Some("last_key"));
+    assert_eq!(
+        symbols.resolve("main_loop"),
+        Some(SymbolAddr::PrgRom(0x4012))
+    );
+    assert_eq!(symbols.label(0xC012, Some(0x4012)), Some("main_loop"));
+    assert_eq!(symbols.label(0xC012, Some(0x0012)), None);
+}
+
+#[test]
+fn test_nl_bank() {
+    assert_eq!(nl_bank("game.nes.ram.nl"), Ok(None));
+    assert_eq!(nl_bank("game.nes.2.nl"), Ok(Some(2)));
+    assert_eq!(nl_bank("game.nes.1F.nl"), Ok(Some(0x1F)));
+    assert!(nl_bank("game.nes.ab.nl").is_err());
+    assert!(nl_bank("game.ab.nl").is_err());
+    assert!(nl_bank("labels.nl").is_err());
+}
+
+#[test]
+fn test_mesen_mlb() {
+    let mut symbols = SymbolTable::new();
+    symbols
+        .load_mesen_mlb("P:0010:nmi_handler:\nR:0020:frame_counter:frames\nS:0000:save_slot\nG:2002:PPUSTATUS\nZ:0000:ignored\n")
+        .unwrap();
+
+    assert_eq!(
+        symbols.resolve("nmi_handler"),
+        Some(SymbolAddr::PrgRom(0x10))
+    );
+    assert_eq!(symbols.label(0x0020, None), Some("frame_counter"));
+    assert_eq!(symbols.label(0x6000, None), Some("save_slot"));
+    assert_eq!(symbols.label(0x2002, None), Some("PPUSTATUS"));
+    assert_eq!(symbols.len(), 4);
+}
+
+#[test]
+fn test_mesen_mlb_out_of_range() {
+    let mut symbols = SymbolTable::new();
+    assert_eq!(
+        symbols.load_mesen_mlb("S:1FFF:last\nW:2000:past_end\n"),
+        Err("bad address in .mlb at line 2".to_string())
+    );
+    assert_eq!(
+        SymbolTable::new().load_mesen_mlb("R:10000:too_far\n"),
+        Err("bad address in .mlb at line 1".to_string())
+    );
+}
+
+#[test]
+fn test_ca65_dbg() {
+    let dbg = "version\tmajor=2,minor=0\n\
+seg\tid=0,name=\"ZEROPAGE\",start=0x000000,size=0x0010,addrsize=zeropage,type=rw\n\
+seg\tid=1,name=\"CODE\",start=0x00C000,size=0x0100,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16400\n\
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,val=0xC010,seg=1,type=lab\n\
+sym\tid=1,name=\"temp\",addrsize=zeropage,scope=0,def=2,val=0x4,seg=0,type=lab\n\
+sym\tid=2,name=\"SPEED\",addrsize=zeropage,scope=0,def=3,val=0x2,type=equ\n";
+    let mut symbols = SymbolTable::new();
+    symbols.load_ca65_dbg(dbg).unwrap();
+
+    assert_eq!(symbols.resolve("main"), Some(SymbolAddr::PrgRom(0x4010)));
+    assert_eq!(symbols.resolve("temp"), Some(SymbolAddr::Cpu(0x04)));
+    assert_eq!(symbols.resolve("SPEED"), None);
+}
+
+#[test]
+fn test_format_addr() {
+    let mut symbols = SymbolTable::new();
+    symbols.add("main_loop", SymbolAddr::Cpu(0x8012), None);
+    assert_eq!(symbols.format_addr(0x8012, None), "$8012 <main_loop>");
+    assert_eq!(symbols.format_addr(0xC000, None), "$C000");
+}
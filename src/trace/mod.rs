@@ -0,0 +1,112 @@
+use crate::bus::Bus;
+use crate::cpu::{AddressingMode, CPU};
+use crate::mem::Mem;
+use crate::opcodes::{self, OpCode};
+use crate::symbols::SymbolTable;
+
+pub mod test;
+
+pub struct Disassembly {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+fn is_accumulator_op(code: u8) -> bool {
+    matches!(code, 0x0a | 0x4a | 0x2a | 0x6a)
+}
+
+pub fn is_branch(code: u8) -> bool {
+    matches!(code, 0x10 | 0x30 | 0x50 | 0x70 | 0x90 | 0xb0 | 0xd0 | 0xf0)
+}
+
+pub fn branch_target(addr: u16, offset: u8) -> u16 {
+    addr.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
+
+// Formats an instruction in ca65 syntax. `name` gets a chance to replace
+// every address operand with a label.
+pub fn format_instruction<F>(opcode: &OpCode, addr: u16, operands: &[u8], name: F) -> String
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let byte = || operands[0];
+    let word = || (operands[1] as u16) << 8 | operands[0] as u16;
+    let zp = |value: u8| name(value as u16).unwrap_or_else(|| format!("${:02X}", value));
+    let abs = |value: u16| name(value).unwrap_or_else(|| format!("${:04X}", value));
+
+    let operand = match opcode.mode {
+        AddressingMode::Immediate => format!("#${:02X}", byte()),
+        AddressingMode::ZeroPage => zp(byte()),
+        AddressingMode::ZeroPage_X => format!("{},X", zp(byte())),
+        AddressingMode::ZeroPage_Y => format!("{},Y", zp(byte())),
+        AddressingMode::Absolute => abs(word()),
+        AddressingMode::Absolute_X => format!("{},X", abs(word())),
+        AddressingMode::Absolute_Y => format!("{},Y", abs(word())),
+        AddressingMode::Indirect_X => format!("({},X)", zp(byte())),
+        AddressingMode::Indirect_Y => format!("({}),Y", zp(byte())),
+        AddressingMode::NoneAddressing => match opcode.code {
+            code if is_accumulator_op(code) => "A".to_string(),
+            code if is_branch(code) => abs(branch_target(addr, byte())),
+            0x4c | 0x20 => abs(word()),
+            0x6c => format!("({})", abs(word())),
+            _ => String::new(),
+        },
+    };
+
+    if operand.is_empty() {
+        opcode.human.to_string()
+    } else {
+        format!("{} {}", opcode.human, operand)
+    }
+}
+
+pub fn disassemble(bus: &Bus, addr: u16, symbols: Option<&SymbolTable>) -> Disassembly {
+    let code = bus.peek(addr);
+    let opcode = match opcodes::OPCODES_MAP.get(&code) {
+        Some(opcode) => opcode,
+        None => {
+            return Disassembly {
+                addr,
+                bytes: vec![code],
+                text: format!(".byte ${:02X}", code),
+            }
+        }
+    };
+
+    let bytes: Vec<u8> = (0..opcode.len as u16)
+        .map(|i| bus.peek(addr.wrapping_add(i)))
+        .collect();
+    let text = format_instruction(opcode, addr, &bytes[1..], |target| {
+        symbols
+            .and_then(|s| s.label(target, bus.prg_rom_offset(target)))
+            .map(|label| label.to_string())
+    });
+    Disassembly { addr, bytes, text }
+}
+
+// One line per instruction, nestest.log style, with the label of the
+// current address in front when there is one.
+pub fn trace(cpu: &CPU, symbols: Option<&SymbolTable>) -> String {
+    let pc = cpu.program_counter;
+    let dis = disassemble(&cpu.bus, pc, symbols);
+
+    let hex: Vec<String> = dis.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let label = symbols
+        .and_then(|s| s.label(pc, cpu.bus.prg_rom_offset(pc)))
+        .map(|l| format!("{}:", l))
+        .unwrap_or_default();
+
+    format!(
+        "{:04X}  {:8}  {:<12}{:<20}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        pc,
+        hex.join(" "),
+        label,
+        dis.text,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status.bits(),
+        cpu.stack_ptr,
+    )
+}
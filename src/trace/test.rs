@@ -0,0 +1,50 @@
+use crate::{
+    bus::Bus,
+    cartridge::test::gen_test_rom_with_program,
+    cpu::CPU,
+    symbols::{SymbolAddr, SymbolTable},
+    trace::{disassemble, trace},
+};
+
+#[test]
+fn test_disassemble_modes() {
+    // LDA #$05 ; STA $0200,X ; LDA ($10),Y ; BNE -4 ; ASL A ; JMP ($0300)
+    let program = vec![
+        0xa9, 0x05, 0x9d, 0x00, 0x02, 0xb1, 0x10, 0xd0, 0xfc, 0x0a, 0x6c, 0x00, 0x03,
+    ];
+    let bus = Bus::new(gen_test_rom_with_program(&program));
+
+    assert_eq!(disassemble(&bus, 0x8000, None).text, "LDA #$05");
+    assert_eq!(disassemble(&bus, 0x8002, None).text, "STA $0200,X");
+    assert_eq!(disassemble(&bus, 0x8005, None).text, "LDA ($10),Y");
+    assert_eq!(disassemble(&bus, 0x8007, None).text, "BNE $8005");
+    assert_eq!(disassemble(&bus, 0x8009, None).text, "ASL A");
+    assert_eq!(disassemble(&bus, 0x800a, None).text, "JMP ($0300)");
+    assert_eq!(
+        disassemble(&bus, 0x800a, None).bytes,
+        vec![0x6c, 0x00, 0x03]
+    );
+}
+
+#[test]
+fn test_trace_uses_symbols() {
+    // JSR $8004 ; BRK ; LDA $10
+    let program = vec![0x20, 0x04, 0x80, 0x00, 0xa5, 0x10];
+    let bus = Bus::new(gen_test_rom_with_program(&program));
+    let mut cpu = CPU::new(bus);
+    cpu.power_on();
+
+    let mut symbols = SymbolTable::new();
+    symbols.add("reset", SymbolAddr::PrgRom(0x0000), None);
+    symbols.add("update", SymbolAddr::PrgRom(0x0004), None);
+    symbols.add("counter", SymbolAddr::Cpu(0x0010), None);
+
+    let line = trace(&cpu, Some(&symbols));
+    assert!(line.starts_with("8000  20 04 80  reset:      JSR update"));
+    assert!(line.ends_with("A:00 X:00 Y:00 P:24 SP:FD"));
+
+    assert_eq!(
+        disassemble(&cpu.bus, 0x8004, Some(&symbols)).text,
+        "LDA counter"
+    );
+}